@@ -1,8 +1,8 @@
 """Models for AI."""
 from abc import ABC, ABCMeta, abstractmethod, abstractproperty
-from dataclasses import dataclass
+from dataclasses import dataclass, field
 from enum import Enum
-from typing import cast, Optional, Iterator, List, Literal
+from typing import Any, cast, Dict, Optional, Iterator, List, Literal
 
 SenderTypeLiteral = Literal["assistant", "user", "system"]
 
@@ -25,6 +25,9 @@ class ChatMessage:
 
     text: str
     sender: SenderType
+    name: Optional[str] = None  # of the sender, e.g., a persona
+    attachments: List[str] = field(default_factory=list)  # file paths
+    hidden: bool = False  # sent to the model but not shown, e.g., file contents
 
 
 @dataclass
@@ -33,6 +36,18 @@ class ModelMetrics:
 
     init_latency_ms: float
 
+    """Estimated memory used by the kv cache, if known."""
+    kv_cache_bytes: Optional[int] = None
+
+    """Problems noticed while loading the model. E.g., misconfigured settings."""
+    warnings: List[str] = field(default_factory=list)
+
+    """Model family whose recommended sampler settings are used, e.g., qwen."""
+    model_family: Optional[str] = None
+
+    """Chat template in the model file, empty if it has none, None if unknown."""
+    chat_template: Optional[str] = None
+
 
 @dataclass
 class CompletionMetrics:
@@ -57,6 +72,20 @@ class CompletionMetrics:
     """Time taken for this completion."""
     completion_latency_ms: float
 
+    """Number of prompt tokens reused from the model's cache."""
+    prompt_cached_tokens: int = 0
+
+
+@dataclass
+class TokenLogprob:
+    """Log probability of a generated token."""
+
+    token: str
+    logprob: float
+
+    """Most likely candidates for this position with their log probabilities."""
+    top_logprobs: Dict[str, float] = field(default_factory=dict)
+
 
 @dataclass
 class CompletionResponse:
@@ -65,6 +94,14 @@ class CompletionResponse:
     text: str
     finish_reason: Optional[str]  # stop, length, none
     metrics: CompletionMetrics
+    logprobs: Optional[List[TokenLogprob]] = None  # if requested in settings
+
+    """Sampler settings, seed and context parameters used for the completion.
+
+    Reported in the first chunk by providers that support it, to reproduce a
+    response.
+    """
+    sampler: Optional[Dict[str, Any]] = None
 
 
 class CompletionModel(ABC, metaclass=ABCMeta):
@@ -102,6 +139,11 @@ class CompletionModel(ABC, metaclass=ABCMeta):
         """Free any resources for the model."""
         raise NotImplementedError
 
+    @property
+    def frees_memory(self) -> bool:
+        """Whether `free` releases the model memory, e.g., for a local model."""
+        return False
+
     @staticmethod
     @abstractmethod
     def validate_config(config: dict) -> bool:
@@ -126,6 +168,9 @@ class EmbeddingModel(ABC, metaclass=ABCMeta):
 
 def combine_metrics(usage_series: List[CompletionMetrics]) -> CompletionMetrics:
     """Join a series of completion metrics into one."""
+    if not usage_series:
+        # Canceled before the first chunk
+        return CompletionMetrics(0, 0, 0, 0, 0)
     response_latency = 0
     response_tokens = 0
     for u in usage_series:
@@ -137,4 +182,5 @@ def combine_metrics(usage_series: List[CompletionMetrics]) -> CompletionMetrics:
         completion_tokens=response_tokens,
         completion_runs=len(usage_series),
         completion_latency_ms=response_latency,
+        prompt_cached_tokens=usage_series[0].prompt_cached_tokens,
     )
@@ -1,6 +1,9 @@
 """Services for the chat command."""
-from dataclasses import dataclass, field
-from typing import List, Optional, Iterator, Tuple
+import os
+import re
+import time
+from dataclasses import dataclass, field, replace
+from typing import Any, Dict, List, Optional, Iterator, Tuple
 
 from arey.ai import (
     ChatMessage,
@@ -8,12 +11,25 @@ from arey.ai import (
     CompletionModel,
     ModelMetrics,
     SenderType,
+    TokenLogprob,
     combine_metrics,
 )
-from arey.config import get_config
+from arey.config import LATENCY_MAX_TOKENS, get_config, parse_yaml
+from arey.error import AreyError, ConfigError, TemplateError
 from arey.platform.console import capture_stderr
+from arey.platform.content_filter import filter_response, get_safety_instructions
+from arey.platform.documents import extract_text
+from arey.platform.environment import get_env_snapshot
+from arey.platform.formats import get_format_instructions, get_format_settings
 from arey.platform.llm import get_completion_llm
-from arey.prompt import Prompt, get_prompt
+from arey.prompt import (
+    Prompt,
+    check_chat_template,
+    find_instructions,
+    get_prefix_tokens,
+    get_prompt,
+    get_variables,
+)
 
 config = get_config()
 prompt_template = config.chat.model.template
@@ -25,6 +41,17 @@ model: CompletionModel = get_completion_llm(
 )
 prompt_model = get_prompt(prompt_template)
 
+MENTION_PATTERN = re.compile(r"(?<!\S)@(\S+)")
+MAX_MENTION_CHARS = 64 * 1024
+SUMMARY_INSTRUCTION = (
+    "Summarize the conversation below. Keep the facts, decisions and open"
+    " questions needed to continue it.\n\n"
+)
+TRANSLATE_INSTRUCTION = (
+    "Translate the text below to {language}. Keep the markdown formatting. Reply"
+    " with only the translation.\n\n"
+)
+
 
 @dataclass
 class MessageContext:
@@ -34,6 +61,8 @@ class MessageContext:
     finish_reason: Optional[str]
     metrics: CompletionMetrics
     logs: str = ""
+    logprobs: List[TokenLogprob] = field(default_factory=list)
+    sampler: Dict[str, Any] = field(default_factory=dict)  # if reported by model
 
 
 @dataclass(kw_only=True)
@@ -42,6 +71,8 @@ class Message(ChatMessage):
 
     timestamp: int  # unix timestamp
     context: Optional[MessageContext]
+    seeded: bool = False  # loaded from a chat template, or a compacted summary
+    token_count: Optional[int] = None  # of the formatted message, cached
 
 
 @dataclass
@@ -50,6 +81,13 @@ class ChatContext:
 
     metrics: Optional[ModelMetrics] = None
     logs: str = ""
+    token_overrides: Dict[str, str] = field(default_factory=dict)
+    default_token_overrides: Dict[str, str] = field(default_factory=dict)  # /system
+    instructions_file: Optional[str] = None  # project instructions, e.g., AREY.md
+    prefill: str = ""  # start of each response, e.g., `{` for json
+    response_format: Optional[str] = None  # format preset, e.g., json
+    profile: Optional[str] = None  # from /profile, else the chat profile in config
+    latency: Optional[str] = None  # from /profile --latency, overrides max_tokens
 
 
 @dataclass
@@ -70,34 +108,171 @@ def _get_max_tokens(model: CompletionModel, prompt_model: Prompt, text: str) ->
     return context_size - prompt_tokens_without_history - buffer
 
 
-def create_chat() -> Tuple[Chat, ModelMetrics]:
-    """Create a new chat session."""
-    system_prompt = prompt_model.get_message("system", "")
-    with capture_stderr() as stderr:
-        model.load(system_prompt)
+def _get_system_message(chat: Chat) -> str:
+    return prompt_model.get_message(
+        "system",
+        "",
+        get_variables(config.chat.model_name) | _get_token_overrides(chat),
+    )
+
+
+def reload_model(chat: Chat) -> None:
+    """Load the model again after it's freed, e.g., when idle.
+
+    The current system prompt warms up the cache for the next response.
+    """
+    with capture_stderr():
+        model.load(_get_system_message(chat))
+
+
+def create_chat(use_instructions: bool = True) -> Tuple[Chat, ModelMetrics]:
+    """Create a new chat session.
+
+    Project instructions, e.g., `AREY.md`, found from the current directory are
+    added to the system prompt unless use_instructions is False.
+    """
     chat = Chat()
-    chat.context.metrics = model.metrics
+    found = find_instructions(os.getcwd()) if use_instructions else None
+    if found:
+        chat.context.instructions_file = found[0]
+    chat.context.token_overrides = get_prefix_tokens(
+        prompt_model.custom_tokens,
+        config.chat.language,
+        found[1] if found else None,
+        get_safety_instructions(config.chat.content_filter.level),
+    )
+    chat.context.default_token_overrides = dict(chat.context.token_overrides)
+
+    with capture_stderr() as stderr:
+        model.load(_get_system_message(chat))
+    metrics = model.metrics
+    warning = check_chat_template(prompt_model, metrics.chat_template)
+    if warning:
+        metrics = replace(metrics, warnings=metrics.warnings + [warning])
+    chat.context.metrics = metrics
     chat.context.logs = stderr.getvalue()
-    return chat, model.metrics
+    return chat, metrics
 
 
-def get_history(
-    model: CompletionModel, chat: Chat, prompt_model: Prompt, max_tokens: int
-) -> str:
-    """Get the messages for a chat."""
-    messages = []
+def _trim_messages(
+    model: CompletionModel,
+    prompt_model: Prompt,
+    messages: List[Message],
+    max_tokens: int,
+) -> Tuple[List[str], int]:
+    """Format the recent messages that fit within max_tokens.
+
+    Token counts are cached in the messages since tokenizing the whole history
+    on every turn adds latency to long chats.
+    """
+    result = []
     token_count = 0
-    for message in reversed(chat.messages):
+    for message in reversed(messages):
         role = message.sender.role()
         formatted_message = prompt_model.get_message(role, message.text)
-        message_tokens = model.count_tokens(formatted_message)
-        messages.insert(0, formatted_message)
+        if message.token_count is None:
+            message.token_count = model.count_tokens(formatted_message)
+        result.append(formatted_message)
 
-        token_count += message_tokens
+        token_count += message.token_count
         if message.sender == SenderType.USER and token_count >= max_tokens:
             break
 
-    return "".join(messages)
+    result.reverse()
+    return result, token_count
+
+
+def get_history(
+    model: CompletionModel, chat: Chat, prompt_model: Prompt, max_tokens: int
+) -> str:
+    """Get the messages for a chat.
+
+    Older messages are trimmed if history exceeds the max_tokens. Seeded
+    messages from a chat template are trimmed only after all other messages.
+    """
+    seeded = [m for m in chat.messages if m.seeded]
+    seeded_messages, seeded_tokens = _trim_messages(
+        model, prompt_model, seeded, max_tokens
+    )
+    messages, _ = _trim_messages(
+        model,
+        prompt_model,
+        [m for m in chat.messages if not m.seeded],
+        max_tokens - seeded_tokens,
+    )
+
+    return "".join(seeded_messages + messages)
+
+
+def load_template(chat: Chat, template_file: str) -> int:
+    """Seed the chat with messages from a template file.
+
+    Returns the count of seeded messages.
+    """
+    with open(template_file, "r", encoding="utf-8") as f:
+        content = parse_yaml(f.read(), template_file, "template") or {}
+
+    messages = content.get("messages", []) if isinstance(content, dict) else []
+    if not messages:
+        raise TemplateError(
+            f"`messages` element is required in chat template: {template_file}.",
+        )
+
+    senders = {"user": SenderType.USER, "assistant": SenderType.ASSISTANT}
+    for message in messages:
+        role = message.get("role")
+        if role not in senders:
+            raise TemplateError(
+                f"Chat template message must have `user` or `assistant` role: {role}.",
+            )
+        chat.messages.append(
+            Message(
+                text=message.get("text", ""),
+                sender=senders[role],
+                timestamp=int(time.time()),
+                context=None,
+                seeded=True,
+            )
+        )
+    return len(messages)
+
+
+def load_script(script_file: str) -> List[str]:
+    """Get the user turns from a conversation script file."""
+    with open(script_file, "r", encoding="utf-8") as f:
+        content = parse_yaml(f.read(), script_file, "template") or {}
+
+    turns = content.get("turns", []) if isinstance(content, dict) else []
+    if (
+        not isinstance(turns, list)
+        or not turns
+        or not all(isinstance(t, str) for t in turns)
+    ):
+        raise TemplateError(
+            f"`turns` element with a list of user messages is required in"
+            f" conversation script: {script_file}.",
+        )
+    return turns
+
+
+def export_transcript(chat: Chat, output_file: str) -> None:
+    """Save the chat messages as a markdown transcript.
+
+    Hidden messages are skipped; their attachments are listed after the next
+    shown message.
+    """
+    attachments: List[str] = []
+    with open(output_file, "w", encoding="utf-8") as f:
+        for message in chat.messages:
+            attachments += message.attachments
+            if message.hidden:
+                continue
+            sender = message.name or message.sender.role().title()
+            f.write(f"## {sender}\n\n{message.text}\n\n")
+            if attachments:
+                paths = ", ".join(f"`{path}`" for path in attachments)
+                f.write(f"Attached: {paths}\n\n")
+                attachments = []
 
 
 def create_response(chat: Chat, message: str) -> str:
@@ -109,44 +284,341 @@ def create_response(chat: Chat, message: str) -> str:
     return response
 
 
+def get_completion_settings(chat: Chat) -> Dict[str, Any]:
+    """Get the completion settings of the chat profile and latency preset."""
+    settings = dict(
+        config.profiles[chat.context.profile]
+        if chat.context.profile
+        else completion_settings
+    )
+    if chat.context.latency:
+        settings["max_tokens"] = LATENCY_MAX_TOKENS[chat.context.latency]
+    return settings
+
+
+def _get_token_overrides(chat: Chat) -> Dict[str, str]:
+    """Get the token overrides with format instructions, if any."""
+    instructions = get_format_instructions(chat.context.response_format)
+    if not instructions:
+        return chat.context.token_overrides
+    prompt_prefix = f"{get_system_prompt(chat)}\n\n{instructions}".lstrip()
+    return chat.context.token_overrides | {"prompt_prefix": prompt_prefix}
+
+
 def stream_response(chat: Chat, message: str) -> Iterator[str]:
-    """Stream a chat response."""
+    """Stream a chat response.
+
+    The chat prefill, if any, is appended to the prompt and starts the response.
+    """
     max_tokens = _get_max_tokens(model, prompt_model, message)
-    context = {
+    context = get_variables(config.chat.model_name) | {
         "user_query": message,
         "chat_history": get_history(model, chat, prompt_model, max_tokens),
     }
-    prompt = prompt_model.get("chat", context)
+    prefill = chat.context.prefill
+    prompt = prompt_model.get("chat", context, _get_token_overrides(chat)) + prefill
 
-    user_msg = Message(text=message, sender=SenderType.USER, timestamp=0, context=None)
+    user_msg = Message(
+        text=message, sender=SenderType.USER, timestamp=int(time.time()), context=None
+    )
     chat.messages.append(user_msg)
 
-    ai_msg_text = ""
+    ai_msg_text = prefill
     usage_series = []
+    logprobs: List[TokenLogprob] = []
+    sampler: Dict[str, Any] = {}
     finish_reason = ""
+    settings = (
+        {k: v for k, v in get_completion_settings(chat).items() if k != "n_candidates"}
+        | get_format_settings(chat.context.response_format)
+        | {"stop": prompt_model.stop_words}
+    )
+    # Responses are checked against the blocklist after completion, hold the
+    # chunks back until then
+    blocklist = config.chat.content_filter.blocklist
+
+    def _add_response(logs: str) -> None:
+        text, filter_log = filter_response(ai_msg_text, blocklist)
+        msg_context = MessageContext(
+            prompt=prompt,
+            finish_reason=finish_reason,
+            metrics=combine_metrics(usage_series),
+            logs=logs + filter_log,
+            logprobs=logprobs,
+            sampler=sampler,
+        )
+        chat.messages.append(
+            Message(
+                text=text,
+                timestamp=int(time.time()),
+                sender=SenderType.ASSISTANT,
+                context=msg_context,
+            )
+        )
+
+    if prefill and not blocklist:
+        yield prefill
     with capture_stderr() as stderr:
-        for chunk in model.complete(prompt, {"stop": prompt_model.stop_words}):
-            ai_msg_text += chunk.text
-            finish_reason = chunk.finish_reason
-            usage_series.append(chunk.metrics)
-            yield chunk.text
-
-    msg_context = MessageContext(
-        prompt=prompt,
-        finish_reason=finish_reason,
-        metrics=combine_metrics(usage_series),
-        logs=stderr.getvalue(),
+        try:
+            for chunk in model.complete(prompt, settings):
+                ai_msg_text += chunk.text
+                finish_reason = chunk.finish_reason
+                usage_series.append(chunk.metrics)
+                logprobs.extend(chunk.logprobs or [])
+                sampler = chunk.sampler or sampler
+                yield "" if blocklist else chunk.text
+        except GeneratorExit:
+            # Canceled, keep the partial response and its token usage
+            finish_reason = "canceled"
+            _add_response(stderr.getvalue())
+            raise
+
+    _add_response(stderr.getvalue())
+    if blocklist:
+        yield chat.messages[-1].text
+
+
+def add_context(chat: Chat, text: str, attachments: List[str] = []) -> None:
+    """Add a hidden user message with context for the next responses.
+
+    The message is sent to the model but not shown in the transcript.
+    """
+    chat.messages.append(
+        Message(
+            text=text,
+            sender=SenderType.USER,
+            timestamp=int(time.time()),
+            context=None,
+            attachments=list(attachments),
+            hidden=True,
+        )
+    )
+
+
+def add_env_context(chat: Chat) -> str:
+    """Attach a snapshot of the user's environment to the chat.
+
+    Returns the snapshot. Set the variables and commands in the `env` config.
+    """
+    snapshot = get_env_snapshot(config.env.variables, config.env.commands)
+    add_context(chat, f"My environment:\n\n```\n{snapshot}\n```")
+    return snapshot
+
+
+def expand_mentions(chat: Chat, text: str) -> Tuple[str, List[str], List[str]]:
+    """Attach the files mentioned as `@path` in text to the chat.
+
+    Mentions are replaced with the file path in the returned text. Mentions
+    that aren't files are left as is. Text is extracted from html, docx and pdf
+    files. Returns the text, attached paths and errors for files that couldn't
+    be read.
+    """
+    attached = []
+    errors = []
+
+    def _attach(match: re.Match) -> str:
+        path = match.group(1)
+        file_path = os.path.expanduser(path)
+        if not os.path.isfile(file_path):
+            return match.group(0)
+
+        try:
+            content = extract_text(file_path, MAX_MENTION_CHARS + 1)
+        except AreyError as e:
+            errors.append(e.message)
+            return match.group(0)
+        if len(content) > MAX_MENTION_CHARS:
+            content = content[:MAX_MENTION_CHARS] + "\n[truncated]"
+        content = content.rstrip("\n")
+        add_context(
+            chat, f"Contents of `{path}`:\n\n```\n{content}\n```", [path]
+        )
+        attached.append(path)
+        return f"`{path}`"
+
+    return MENTION_PATTERN.sub(_attach, text), attached, errors
+
+
+def get_system_prompt(chat: Chat) -> str:
+    """Get the system prompt, i.e., the `prompt_prefix` token, of the chat."""
+    tokens = prompt_model.custom_tokens | chat.context.token_overrides
+    return tokens.get("prompt_prefix", "")
+
+
+def set_system_prompt(chat: Chat, text: Optional[str]) -> None:
+    """Replace the system prompt of the chat for the next responses.
+
+    Restores the prompt from chat creation, including the language and project
+    instructions, if text is None.
+    """
+    if text is None:
+        chat.context.token_overrides = dict(chat.context.default_token_overrides)
+        return
+    chat.context.token_overrides = chat.context.token_overrides | {
+        "prompt_prefix": text
+    }
+
+
+def get_candidate_count(chat: Chat) -> int:
+    """Get the number of responses to generate for each user message.
+
+    Set with `n_candidates` in the chat profile, or the one chosen with /profile.
+    """
+    count = get_completion_settings(chat).get("n_candidates", 1)
+    if not isinstance(count, int) or isinstance(count, bool) or count < 1:
+        raise ConfigError(f"Invalid `n_candidates`: {count}. Use a positive integer.")
+    return count
+
+
+def pop_candidate(chat: Chat) -> Optional[Message]:
+    """Remove the last user message and its response, and get the response.
+
+    Returns None if the response was canceled before completion.
+    """
+    response = None
+    if chat.messages and chat.messages[-1].sender == SenderType.ASSISTANT:
+        response = chat.messages.pop()
+    if (
+        chat.messages
+        and chat.messages[-1].sender == SenderType.USER
+        and not chat.messages[-1].hidden
+    ):
+        chat.messages.pop()
+    if response and response.context and response.context.finish_reason == "canceled":
+        return None
+    return response
+
+
+def keep_candidate(chat: Chat, message: str, response: Message) -> None:
+    """Add the user message and the chosen response to the chat."""
+    chat.messages.append(
+        Message(
+            text=message,
+            sender=SenderType.USER,
+            timestamp=int(time.time()),
+            context=None,
+        )
+    )
+    chat.messages.append(response)
+
+
+def undo_last_turn(chat: Chat) -> bool:
+    """Remove the last user message and its response from the chat.
+
+    Hidden context attached right before the message, e.g., mentioned files,
+    is removed too. The removed messages aren't sent in the next prompt;
+    llama.cpp models recompute the kv cache from the first changed token.
+    Seeded messages are never removed.
+    """
+    messages = chat.messages
+    user_index = next(
+        (
+            i
+            for i in range(len(messages) - 1, -1, -1)
+            if messages[i].sender == SenderType.USER and not messages[i].hidden
+        ),
+        None,
     )
-    ai_msg = Message(
-        text=ai_msg_text, timestamp=0, sender=SenderType.ASSISTANT, context=msg_context
+    if user_index is None or messages[user_index].seeded:
+        return False
+
+    start = user_index
+    while start > 0 and messages[start - 1].hidden and not messages[start - 1].seeded:
+        start -= 1
+    end = user_index + 1
+    if end < len(messages) and messages[end].sender == SenderType.ASSISTANT:
+        end += 1
+    del messages[start:end]
+    return True
+
+
+def _run_task(instruction: str) -> str:
+    """Get the response of the task model for an instruction."""
+    from arey.task import create_task, run
+
+    task, _ = create_task(None)
+    return "".join(run(task, instruction)).strip()
+
+
+def _get_transcript(
+    task_model: CompletionModel, messages: List[Message], max_tokens: int
+) -> str:
+    """Format the recent messages that fit within max_tokens as a transcript.
+
+    Hidden context, e.g., attached files, is shortened to its first line
+    except the summary of an earlier compaction. The last message is kept even
+    if it doesn't fit.
+    """
+    lines: List[str] = []
+    token_count = 0
+    for message in reversed(messages):
+        text = message.text
+        if message.hidden and not message.seeded:
+            text = f"{text.splitlines()[0] if text else ''} (omitted)"
+        line = f"{message.sender.role()}: {text}"
+        token_count += task_model.count_tokens(line)
+        if lines and token_count > max_tokens:
+            break
+        lines.append(line)
+
+    return "\n\n".join(reversed(lines))
+
+
+def summarize_chat(chat: Chat) -> Optional[str]:
+    """Summarize the conversation with the task model.
+
+    Messages seeded from a template are excluded. Older messages are left out
+    if the conversation doesn't fit in three quarters of the task model
+    context, the rest is for the prompt and summary. Returns None if there is
+    nothing to summarize.
+    """
+    from arey.task import create_task, run
+    from arey.task import model as task_model
+
+    # Seeded and hidden is the summary of an earlier compaction
+    messages = [m for m in chat.messages if not m.seeded or m.hidden]
+    if not messages:
+        return None
+
+    task, _ = create_task(None)
+    max_tokens = task_model.context_size * 3 // 4 - task_model.count_tokens(
+        SUMMARY_INSTRUCTION
     )
-    chat.messages.append(ai_msg)
+    transcript = _get_transcript(task_model, messages, max_tokens)
+    return "".join(run(task, SUMMARY_INSTRUCTION + transcript)).strip()
+
+
+def translate_last_response(chat: Chat, language: str) -> Optional[str]:
+    """Translate the last assistant message with the task model.
+
+    The chat isn't changed. Returns None if there is no response yet.
+    """
+    message = get_last_response(chat)
+    if not message:
+        return None
+    return _run_task(TRANSLATE_INSTRUCTION.format(language=language) + message.text)
+
+
+def compact_chat(chat: Chat, summary: str) -> None:
+    """Replace the conversation with its summary to free up context.
+
+    Messages seeded from a template are kept. The summary is seeded too, so
+    `/undo` doesn't remove it and a later compaction replaces it.
+    """
+    chat.messages = [m for m in chat.messages if m.seeded and not m.hidden]
+    add_context(chat, f"Summary of the conversation so far:\n\n{summary}")
+    chat.messages[-1].seeded = True
 
 
 def get_completion_metrics(chat: Chat) -> Optional[CompletionMetrics]:
     """Get completion metrics for the chat."""
-    msg = next(
+    msg = get_last_response(chat)
+    return msg.context.metrics if msg and msg.context else None
+
+
+def get_last_response(chat: Chat) -> Optional[Message]:
+    """Get the last assistant message in the chat."""
+    return next(
         filter(lambda m: m.sender == SenderType.ASSISTANT, reversed(chat.messages)),
         None,
     )
-    return msg.context.metrics if msg and msg.context else None
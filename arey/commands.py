@@ -0,0 +1,518 @@
+"""Commands for the chat REPL.
+
+A command is an input starting with `/`, e.g., `/inspect`. Arguments, if any,
+follow the command name.
+"""
+import math
+import subprocess
+from dataclasses import dataclass, field
+from typing import Callable, Dict, List
+
+import click
+from rich.console import Console
+from rich.markdown import Markdown
+from rich.table import Table
+
+from arey.chat import (
+    Chat,
+    add_context,
+    add_env_context,
+    compact_chat,
+    config,
+    get_candidate_count,
+    get_last_response,
+    get_completion_settings,
+    get_system_prompt,
+    model,
+    set_system_prompt,
+    summarize_chat,
+    translate_last_response,
+    undo_last_turn,
+)
+from arey.config import LATENCY_MAX_TOKENS
+from arey.error import ConfigError
+from arey.platform.formats import get_format_instructions, get_format_names
+from arey.prompt import get_template_marker
+
+
+@dataclass
+class ChatCommand:
+    """A command available in the chat REPL."""
+
+    name: str
+    help: str
+    run: Callable[[Console, Chat, str], None]
+    usage: str = ""  # argument forms, e.g., [--compact]
+    examples: List[str] = field(default_factory=list)
+
+
+_commands: Dict[str, ChatCommand] = {}
+
+SHELL_PREVIEW_LINES = 20
+_last_shell_output = ""
+
+
+def chat_command(name: str, help: str, usage: str = "", examples: List[str] = []):
+    """Register a function as chat command."""
+
+    def decorator(func: Callable[[Console, Chat, str], None]):
+        _commands[name] = ChatCommand(name, help, func, usage, list(examples))
+        return func
+
+    return decorator
+
+
+def get_command_names() -> List[str]:
+    """Get the names of all chat commands."""
+    return list(_commands.keys())
+
+
+def is_command(user_input: str) -> bool:
+    """Check if the user input is a command."""
+    return user_input.startswith("/")
+
+
+def is_shell_command(user_input: str) -> bool:
+    """Check if the user input is a shell command, e.g., `!ls`."""
+    return user_input.startswith("!")
+
+
+def run_shell_command(console: Console, chat: Chat, user_input: str) -> None:
+    """Run a shell command and optionally attach its output to the chat."""
+    command = user_input[1:].strip()
+    if not command:
+        console.print("Usage: !<command>, e.g., !git status")
+        console.print()
+        return
+
+    try:
+        result = subprocess.run(
+            command, shell=True, capture_output=True, text=True, errors="replace"
+        )
+    except KeyboardInterrupt:
+        console.print("◼ Canceled.", style="message_footer")
+        console.print()
+        return
+
+    global _last_shell_output
+    output = result.stdout + result.stderr
+    _last_shell_output = output.rstrip()
+    lines = _last_shell_output.splitlines()
+    preview = "\n".join(lines[:SHELL_PREVIEW_LINES])
+    console.print(preview, markup=False, highlight=False)
+    if len(lines) > SHELL_PREVIEW_LINES:
+        console.print(
+            f"… {len(lines) - SHELL_PREVIEW_LINES} more lines. Type /expand to see"
+            " the full output.",
+            style="message_footer",
+        )
+    console.print()
+    console.print(f"◼ Exit code {result.returncode}.", style="message_footer")
+    console.print("Attach output to the conversation? [y/N] ", end="")
+    try:
+        attach = click.getchar().lower() == "y"
+    except (KeyboardInterrupt, EOFError):
+        attach = False
+    console.print("y" if attach else "n")
+    if attach:
+        add_context(chat, f"Output of `{command}`:\n\n```\n{output.rstrip()}\n```")
+        console.print("✓ Attached.", style="message_footer")
+    console.print()
+
+
+def run_command(console: Console, chat: Chat, user_input: str) -> None:
+    """Run the command in user input."""
+    name, _, args = user_input[1:].partition(" ")
+    command = _commands.get(name)
+    if not command:
+        console.print(
+            f"Unknown command: /{name}. Type /help to see all commands.",
+            style="error",
+        )
+        console.print()
+        return
+
+    command.run(console, chat, args.strip())
+    console.print()
+
+
+@chat_command(
+    "help",
+    "Show all commands, or details of a command.",
+    usage="[command]",
+    examples=["/help", "/help summarize"],
+)
+def show_help(console: Console, chat: Chat, args: str) -> None:
+    """Print help for all chat commands or the given command."""
+    if args:
+        command = _commands.get(args.lstrip("/"))
+        if not command:
+            console.print(f"Unknown command: /{args.lstrip('/')}.", style="error")
+            return
+        console.print(f"/{command.name} {command.usage}".rstrip(), style="bold")
+        console.print(command.help)
+        if command.examples:
+            console.print()
+            console.print("Examples:")
+            for example in command.examples:
+                console.print(f"  {example}", markup=False)
+        return
+
+    commands = Table(
+        show_header=False, box=None, title="Commands", title_justify="left"
+    )
+    for command in sorted(_commands.values(), key=lambda c: c.name):
+        commands.add_row(f"/{command.name} {command.usage}".rstrip(), command.help)
+    console.print(commands)
+    console.print()
+
+    inputs = Table(show_header=False, box=None, title="Input", title_justify="left")
+    inputs.add_row("@path", "Attach a file to the message.")
+    inputs.add_row("!command", "Run a shell command and optionally attach its output.")
+    inputs.add_row("q, quit", "Exit the chat.")
+    console.print(inputs)
+
+
+@chat_command("env", "Attach a snapshot of the OS, shell and tool versions.")
+def attach_env(console: Console, chat: Chat, args: str) -> None:
+    """Print the environment snapshot and attach it to the chat."""
+    snapshot = add_env_context(chat)
+    console.print(snapshot, markup=False, highlight=False)
+    console.print()
+    console.print("✓ Attached.", style="message_footer")
+
+
+@chat_command(
+    "format",
+    "Show or set the format of the next responses.",
+    usage=f"[{' | '.join(get_format_names())} | --clear]",
+    examples=["/format", "/format json", "/format --clear"],
+)
+def set_format(console: Console, chat: Chat, args: str) -> None:
+    """Print, set or clear the response format preset."""
+    if not args:
+        name = chat.context.response_format
+        instructions = get_format_instructions(name)
+        console.print(f"{name}: {instructions}" if name else "No format.")
+        return
+    if args == "--clear":
+        chat.context.response_format = None
+        console.print("✓ Cleared the format.", style="message_footer")
+        return
+    if args not in get_format_names():
+        console.print(f"Usage: /format [{' | '.join(get_format_names())} | --clear]")
+        return
+
+    chat.context.response_format = args
+    console.print(f"✓ Responses will use the {args} format.", style="message_footer")
+
+
+@chat_command("inspect", "Show token probabilities of the last response.")
+def inspect(console: Console, chat: Chat, args: str) -> None:
+    """Print the token log probabilities of last response."""
+    message = get_last_response(chat)
+    logprobs = message.context.logprobs if message and message.context else []
+    if not logprobs:
+        console.print(
+            "No token probabilities available. Set `logprobs: true` in the chat"
+            " profile to capture them."
+        )
+        return
+
+    def _format(token: str) -> str:
+        return repr(token)[1:-1]
+
+    table = Table()
+    table.add_column("#", justify="right")
+    table.add_column("Token")
+    table.add_column("Probability", justify="right")
+    table.add_column("Top candidates")
+    for index, lp in enumerate(logprobs):
+        candidates = ", ".join(
+            f"{_format(token)} ({math.exp(val):.1%})"
+            for token, val in sorted(
+                lp.top_logprobs.items(), key=lambda x: x[1], reverse=True
+            )
+        )
+        table.add_row(
+            str(index + 1),
+            _format(lp.token),
+            f"{math.exp(lp.logprob):.1%}",
+            candidates,
+        )
+    console.print(table)
+
+
+@chat_command(
+    "log",
+    "Show model logs of the last response. Use --raw to add sampler settings.",
+    usage="[--raw]",
+    examples=["/log", "/log --raw"],
+)
+def show_log(console: Console, chat: Chat, args: str) -> None:
+    """Print the logs, and optionally the sampler settings of last response."""
+    if args not in ("", "--raw"):
+        console.print("Usage: /log [--raw]")
+        return
+
+    message = get_last_response(chat)
+    if not message or not message.context:
+        console.print("No response yet.")
+        return
+
+    context = message.context
+    console.print(context.logs.strip() or "No logs.", markup=False, highlight=False)
+    if args != "--raw":
+        return
+
+    console.print()
+    if context.sampler:
+        table = Table(
+            show_header=False, box=None, title="Sampler", title_justify="left"
+        )
+        for key, value in context.sampler.items():
+            table.add_row(key, str(value))
+        console.print(table)
+    else:
+        console.print("Sampler settings aren't reported by this model.")
+    console.print()
+    console.print("Prompt", style="bold")
+    console.print(context.prompt, markup=False, highlight=False)
+
+
+@chat_command("model", "Show details of the chat model.")
+def show_model(console: Console, chat: Chat, args: str) -> None:
+    """Print the chat model details."""
+    model_config = config.chat.model
+    table = Table(show_header=False, box=None)
+    table.add_row("Name", config.chat.model_name)
+    table.add_row("Type", model_config.type)
+    table.add_row("Model", model_config.path or model_config.name)
+    table.add_row("Template", model_config.template)
+    chat_template = model.metrics.chat_template
+    if chat_template is not None:
+        marker = get_template_marker(chat_template) if chat_template else "none"
+        table.add_row("Model file template", marker or "unknown markers")
+    table.add_row("Context size", str(model.context_size))
+    if model.metrics.model_family:
+        table.add_row("Sampler defaults", model.metrics.model_family)
+    for adapter in config.chat.settings.get("lora_adapters", []):
+        table.add_row(
+            "LoRA adapter", f"{adapter.get('path')} (scale {adapter.get('scale', 1.0)})"
+        )
+    console.print(table)
+
+
+@chat_command(
+    "profile",
+    "Show or change the completion profile and latency for the next responses.",
+    usage=f"[<name> | --latency {'|'.join(LATENCY_MAX_TOKENS)} | --reset]",
+    examples=["/profile", "/profile creative", "/profile --latency snappy"],
+)
+def profile(console: Console, chat: Chat, args: str) -> None:
+    """Print or switch the completion profile and latency preset."""
+    option, _, value = args.partition(" ")
+    value = value.strip()
+    if not args:
+        table = Table(show_header=False, box=None)
+        table.add_row("Profile", chat.context.profile or "from config")
+        table.add_row("Latency", chat.context.latency or "from profile")
+        for key, val in get_completion_settings(chat).items():
+            table.add_row(key, str(val))
+        console.print(table)
+        return
+    if option == "--reset" and not value:
+        chat.context.profile = None
+        chat.context.latency = None
+        console.print("✓ Restored the profile from config.", style="message_footer")
+        return
+    if option == "--latency" and value in LATENCY_MAX_TOKENS:
+        chat.context.latency = value
+        max_tokens = LATENCY_MAX_TOKENS[value]
+        limit = f"up to {max_tokens}" if max_tokens > 0 else "unlimited"
+        console.print(
+            f"✓ Responses use {value} latency, {limit} tokens.",
+            style="message_footer",
+        )
+        return
+    if option.startswith("--"):
+        console.print(
+            f"Usage: /profile [<name> | --latency {'|'.join(LATENCY_MAX_TOKENS)}"
+            " | --reset]"
+        )
+        return
+    if args not in config.profiles:
+        console.print(
+            f"Unknown profile: {args}. Available profiles:"
+            f" {', '.join(config.profiles) or 'none'}.",
+            style="error",
+        )
+        return
+
+    previous = chat.context.profile
+    chat.context.profile = args
+    try:
+        get_candidate_count(chat)
+    except ConfigError as e:
+        chat.context.profile = previous
+        console.print(f"{e.message} Kept the current profile.", style="error")
+        return
+    console.print(f"✓ Responses use the {args} profile.", style="message_footer")
+
+
+@chat_command("stats", "Show token usage and cache reuse for each turn.")
+def stats(console: Console, chat: Chat, args: str) -> None:
+    """Print the completion metrics for each assistant message."""
+    table = Table()
+    table.add_column("Turn", justify="right")
+    table.add_column("Prompt tokens", justify="right")
+    table.add_column("Cached", justify="right")
+    table.add_column("Recomputed", justify="right")
+    table.add_column("Completion tokens", justify="right")
+    table.add_column("Latency", justify="right")
+    turn = 0
+    for message in chat.messages:
+        if not message.context:
+            continue
+        turn += 1
+        metrics = message.context.metrics
+        table.add_row(
+            str(turn),
+            str(metrics.prompt_tokens),
+            str(metrics.prompt_cached_tokens),
+            str(metrics.prompt_tokens - metrics.prompt_cached_tokens),
+            str(metrics.completion_tokens),
+            f"{metrics.completion_latency_ms / 1000:.2f}s",
+        )
+    console.print(table)
+
+
+@chat_command("expand", "Show the full output of the last shell command.")
+def expand(console: Console, chat: Chat, args: str) -> None:
+    """Print the full output of last shell command."""
+    if not _last_shell_output:
+        console.print("No shell output to expand.")
+        return
+    console.print(_last_shell_output, markup=False, highlight=False)
+
+
+@chat_command(
+    "summarize",
+    "Summarize the conversation. Use --compact to replace it with the summary.",
+    usage="[--compact]",
+    examples=["/summarize", "/summarize --compact"],
+)
+def summarize(console: Console, chat: Chat, args: str) -> None:
+    """Print a summary of the chat and optionally compact it."""
+    if args not in ("", "--compact"):
+        console.print("Usage: /summarize [--compact]")
+        return
+
+    with console.status("[message_footer]Summarizing...", spinner="dots"):
+        summary = summarize_chat(chat)
+    if not summary:
+        console.print("Nothing to summarize.")
+        return
+
+    console.print(Markdown(summary))
+    if args == "--compact":
+        compact_chat(chat, summary)
+        console.print()
+        console.print(
+            "✓ Replaced the conversation with its summary.", style="message_footer"
+        )
+
+
+@chat_command(
+    "prefill",
+    "Show or set the start of the next responses, e.g., to force a format.",
+    usage='["<text>" | --clear]',
+    examples=['/prefill "{"', '/prefill "Sure, here is the list:"', "/prefill --clear"],
+)
+def prefill(console: Console, chat: Chat, args: str) -> None:
+    """Print, set or clear the prefill of responses."""
+    if not args:
+        console.print(
+            repr(chat.context.prefill) if chat.context.prefill else "No prefill.",
+            markup=False,
+        )
+        return
+    if args == "--clear":
+        chat.context.prefill = ""
+        console.print("✓ Cleared the prefill.", style="message_footer")
+        return
+    if args.startswith("--"):
+        console.print('Usage: /prefill ["<text>" | --clear]')
+        return
+
+    # Quotes keep the leading and trailing spaces of text
+    if len(args) > 1 and args[0] == args[-1] == '"':
+        args = args[1:-1]
+    chat.context.prefill = args
+    console.print(
+        "✓ Responses will start with the prefill. Type /prefill --clear to stop.",
+        style="message_footer",
+    )
+
+
+@chat_command(
+    "translate",
+    "Translate the last response with the task model.",
+    usage="<language>",
+    examples=["/translate French"],
+)
+def translate(console: Console, chat: Chat, args: str) -> None:
+    """Print the last response translated to another language."""
+    if not args:
+        console.print("Usage: /translate <language>")
+        return
+
+    with console.status("[message_footer]Translating...", spinner="dots"):
+        translation = translate_last_response(chat, args)
+    if not translation:
+        console.print("No response to translate.")
+        return
+    console.print(Markdown(translation))
+
+
+@chat_command(
+    "system",
+    "Show or change the system prompt for the next responses.",
+    usage="[<prompt> | --append <text> | --reset]",
+    examples=[
+        "/system",
+        "/system You are a terse assistant.",
+        "/system --append Answer in bullet points.",
+        "/system --reset",
+    ],
+)
+def system(console: Console, chat: Chat, args: str) -> None:
+    """Print, replace, extend or reset the system prompt."""
+    option, _, text = args.partition(" ")
+    if not args:
+        console.print(get_system_prompt(chat) or "No system prompt.", markup=False)
+        return
+    if option == "--reset" and not text:
+        set_system_prompt(chat, None)
+        console.print("✓ Restored the system prompt.", style="message_footer")
+        return
+    if option == "--append" and text.strip():
+        prompt = f"{get_system_prompt(chat)}\n\n{text.strip()}".lstrip()
+        set_system_prompt(chat, prompt)
+        console.print("✓ Extended the system prompt.", style="message_footer")
+        return
+    if option.startswith("--"):
+        console.print("Usage: /system [<prompt> | --append <text> | --reset]")
+        return
+
+    set_system_prompt(chat, args)
+    console.print("✓ Replaced the system prompt.", style="message_footer")
+
+
+@chat_command("undo", "Remove the last message and its response.")
+def undo(console: Console, chat: Chat, args: str) -> None:
+    """Undo the last turn in the chat."""
+    if not undo_last_turn(chat):
+        console.print("Nothing to undo.")
+        return
+    console.print("✓ Removed the last turn.", style="message_footer")
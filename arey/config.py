@@ -1,14 +1,43 @@
 """Configuration for arey."""
+import difflib
 import os
-from dataclasses import dataclass, field, asdict
-from typing import Dict, Optional, TypedDict, Tuple, Union, cast
+import re
+import shutil
+from dataclasses import dataclass, field, fields, asdict
+from typing import (
+    Any,
+    Callable,
+    Dict,
+    List,
+    Literal,
+    Optional,
+    TypedDict,
+    Tuple,
+    Type,
+    TypeVar,
+    Union,
+    cast,
+    get_args,
+    get_type_hints,
+)
 
 import yaml
 
-from arey.error import AreyError
+from arey.error import ConfigError, TemplateError
 from arey.platform.assets import get_config_dir, get_default_config
+from arey.platform.console import get_console
 from arey.platform.llm import validate_config
 
+# Config files without a `version` are treated as the current version.
+CONFIG_VERSION = 1
+WORKSPACE_CONFIG_FILE = ".arey.yml"
+
+FooterMode = Literal["off", "minimal", "full"]
+# Max tokens to generate for a profile's `latency`, -1 is unlimited
+LATENCY_MAX_TOKENS = {"snappy": 256, "balanced": 1024, "thorough": -1}
+ContentFilterLevel = Literal["off", "standard", "strict"]
+SectionT = TypeVar("SectionT")
+
 
 @dataclass
 class ModelConfig:
@@ -24,13 +53,109 @@ class ModelConfig:
         return asdict(self)
 
 
-class ProfileConfig(TypedDict):
+class ProfileConfig(TypedDict, total=False):
     """Configuration for a given profile."""
 
     temperature: float
     repeat_penalty: float
     top_k: int
     top_p: float
+    min_p: float
+    typical_p: float
+    presence_penalty: float
+    frequency_penalty: float
+    mirostat: int
+    mirostat_tau: float
+    mirostat_eta: float
+    max_tokens: int
+    num_predict: int  # max_tokens for Ollama
+    seed: int
+    stop: List[str]
+    logprobs: Union[bool, int]
+    n_candidates: int
+    format: str  # constrain the output, e.g., json
+    latency: str  # snappy, balanced or thorough, sets max_tokens
+    extra: Dict[str, Any]  # provider specific settings, not validated
+
+
+def resolve_profile(name: str, profiles: dict, seen: Tuple[str, ...] = ()) -> dict:
+    """Get the settings of a profile merged over the profile it `extends`.
+
+    Settings in `extra` are merged too. A profile's `latency` or `max_tokens`
+    replaces both of them from the base. Raises ConfigError for an unknown base
+    profile or a cycle.
+    """
+    if name in seen:
+        cycle = " -> ".join([*seen, name])
+        raise ConfigError(f"Profiles extend each other in a cycle: {cycle}.")
+    settings = dict(profiles[name] or {})
+    base_name = settings.pop("extends", None)
+    if base_name is None:
+        return settings
+    if base_name not in profiles:
+        raise ConfigError(
+            f"Profile '{name}' extends an unknown profile: '{base_name}'."
+        )
+
+    base = resolve_profile(base_name, profiles, (*seen, name))
+    if "latency" in settings or "max_tokens" in settings:
+        base = {k: v for k, v in base.items() if k not in ("latency", "max_tokens")}
+    extra = (base.get("extra") or {}) | (settings.get("extra") or {})
+    result = base | settings
+    if extra:
+        result["extra"] = extra
+    return result
+
+
+def get_profile(name: str, settings: dict, warnings: List[str]) -> ProfileConfig:
+    """Validate the completion settings of a profile.
+
+    Unknown settings, e.g., a typo like `temprature`, are kept with a warning
+    added to warnings. Llama.cpp models reject the settings they don't support,
+    Ollama models ignore them. Settings under `extra` aren't validated and are
+    merged into the profile. `latency` is replaced with its max_tokens unless
+    max_tokens is set.
+    """
+    hints = get_type_hints(ProfileConfig)
+    settings = dict(settings)
+    unknown = {k: settings.pop(k) for k in list(settings) if k not in hints}
+    for key in unknown:
+        match = difflib.get_close_matches(key, hints.keys(), n=1)
+        suggestion = f" Did you mean `{match[0]}`?" if match else ""
+        warnings.append(
+            f"Profile '{name}' has unknown setting `{key}`.{suggestion} Move it"
+            " under `extra` if the model supports it to hide this warning.",
+        )
+    for key, value in settings.items():
+        expected = hints[key]
+        if expected in (int, float) and (
+            isinstance(value, bool) or not isinstance(value, (int, expected))
+        ):
+            raise ConfigError(
+                f"Profile '{name}' has invalid `{key}`: {value}. Use a number.",
+            )
+
+    profile = dict(settings)
+    extra = profile.pop("extra", None) or {}
+    if not isinstance(extra, dict):
+        raise ConfigError(f"Profile '{name}' must have a mapping in `extra`.")
+    latency = profile.pop("latency", None)
+    if latency is not None:
+        if latency not in LATENCY_MAX_TOKENS:
+            raise ConfigError(
+                f"Profile '{name}' has invalid `latency`: {latency}. Use one of"
+                f" {', '.join(LATENCY_MAX_TOKENS)}.",
+            )
+        profile = {"max_tokens": LATENCY_MAX_TOKENS[latency]} | profile
+    return cast(ProfileConfig, profile | unknown | extra)
+
+
+@dataclass
+class ContentFilterConfig:
+    """Configuration for the content filter of responses."""
+
+    level: ContentFilterLevel = "off"  # safety instructions in the system prompt
+    blocklist: List[str] = field(default_factory=list)  # case insensitive regexes
 
 
 @dataclass
@@ -41,6 +166,10 @@ class ChatConfig:
     model: ModelConfig
     profile: ProfileConfig
     settings: Dict = field(default_factory=dict)
+    language: Optional[str] = None  # respond in this language, e.g., French
+    footer: FooterMode = "full"  # metrics after each response
+    content_filter: ContentFilterConfig = field(default_factory=ContentFilterConfig)
+    idle_timeout: int = 0  # minutes without input to free the model, 0 is off
 
 
 @dataclass
@@ -51,6 +180,64 @@ class TaskConfig:
     model: ModelConfig
     profile: ProfileConfig
     settings: Dict = field(default_factory=dict)
+    language: Optional[str] = None  # respond in this language, e.g., French
+    footer: FooterMode = "full"  # metrics after each response
+    content_filter: ContentFilterConfig = field(default_factory=ContentFilterConfig)
+
+
+@dataclass
+class HistoryConfig:
+    """Configuration for the chat input history."""
+
+    max_entries: int = 1000
+    ignore_patterns: List[str] = field(
+        default_factory=lambda: [
+            r"(?i)(password|passwd|secret|api[_-]?key)",
+            r"\b(sk|ghp|gho|xox[bp])[-_][A-Za-z0-9_-]{16,}",
+        ]
+    )
+
+
+@dataclass
+class EnvConfig:
+    """Configuration for the environment snapshot, e.g., `/env`."""
+
+    variables: List[str] = field(
+        default_factory=lambda: ["VIRTUAL_ENV", "CONDA_DEFAULT_ENV", "LANG"]
+    )
+    commands: List[str] = field(
+        default_factory=lambda: [
+            "python --version",
+            "rustc --version",
+            "node --version",
+            "git --version",
+        ]
+    )
+
+
+@dataclass
+class UiConfig:
+    """Configuration for the terminal output."""
+
+    max_width: int = 0  # wrap responses at this width, 0 for terminal width
+    stream_rate: int = 0  # max characters per second for bursty streams, 0 is off
+
+
+def get_section(cls: Type[SectionT], name: str, values: Optional[dict]) -> SectionT:
+    """Create a config section from values.
+
+    Unknown keys, e.g., a typo like `max_entires`, raise a ConfigError.
+    """
+    values = values or {}
+    if not isinstance(values, dict):
+        raise ConfigError(f"`{name}` must be a mapping.")
+    names = [f.name for f in fields(cast(Any, cls))]
+    for key in values:
+        if key not in names:
+            match = difflib.get_close_matches(key, names, n=1)
+            suggestion = f" Did you mean `{match[0]}`?" if match else ""
+            raise ConfigError(f"`{name}` has unknown setting `{key}`.{suggestion}")
+    return cls(**values)
 
 
 @dataclass
@@ -61,6 +248,11 @@ class Config:
     profiles: Dict[str, ProfileConfig]
     chat: ChatConfig
     task: TaskConfig
+    history: HistoryConfig = field(default_factory=HistoryConfig)
+    ui: UiConfig = field(default_factory=UiConfig)
+    env: EnvConfig = field(default_factory=EnvConfig)
+    workspace_file: Optional[str] = None  # .arey.yml overlay, if any
+    warnings: List[str] = field(default_factory=list)  # e.g., unknown settings
 
     @classmethod
     def from_dict(cls, config: dict):
@@ -76,36 +268,30 @@ class Config:
             )
             for key, val in config.get("models", {}).items()
         }
+        warnings: List[str] = []
+        raw_profiles = config.get("profiles", {})
         profiles = {
-            key: ProfileConfig(**val) for key, val in config.get("profiles", {}).items()
-        }
-        default_profile: ProfileConfig = {
-            "temperature": 0.7,
-            "repeat_penalty": 1.176,
-            "top_k": 40,
-            "top_p": 0.1,
+            key: get_profile(key, resolve_profile(key, raw_profiles), warnings)
+            for key in raw_profiles
         }
+        # Models use the recommended settings for their family, if known
+        default_profile: ProfileConfig = {}
 
         if "chat" not in config or "task" not in config:
-            raise AreyError(
-                "config", "`chat` and `task` sections are not available in config file."
+            raise ConfigError(
+                "`chat` and `task` sections are not available in config file."
             )
 
         def _get_config(key: str) -> Union[ChatConfig, TaskConfig]:
             model_name = config[key].get("model", None)
             if not model_name or model_name not in models:
-                raise AreyError(
-                    "config", f"Section '{key}' must have valid `model` entry."
-                )
+                raise ConfigError(f"Section '{key}' must have valid `model` entry.")
             model = models[model_name]
             model.path = os.path.expanduser(model.path) if model.path else model.path
             if not validate_config(asdict(model)):
-                raise AreyError(
-                    "config", f"Model '{model_name}' has invalid config: {model}."
-                )
+                raise ConfigError(f"Model '{model_name}' has invalid config: {model}.")
             if not has_prompt(model.template):
-                raise AreyError(
-                    "config",
+                raise ConfigError(
                     f"Model '{model_name}' has invalid template: {model.template}.",
                 )
 
@@ -113,13 +299,223 @@ class Config:
                 config[key].get("profile", "default"), default_profile
             )
             settings = config[key]["settings"] if "settings" in config[key] else {}
+            language = config[key].get("language")
+            footer = config[key].get("footer", "full")
+            if footer not in get_args(FooterMode):
+                raise ConfigError(
+                    f"Section '{key}' has invalid `footer`: {footer}. Use one of"
+                    f" {', '.join(get_args(FooterMode))}.",
+                )
+            content_filter = get_section(
+                ContentFilterConfig,
+                f"{key}.content_filter",
+                config[key].get("content_filter"),
+            )
+            if content_filter.level not in get_args(ContentFilterLevel):
+                raise ConfigError(
+                    f"Section '{key}' has invalid `content_filter.level`:"
+                    f" {content_filter.level}. Use one of"
+                    f" {', '.join(get_args(ContentFilterLevel))}.",
+                )
+            for pattern in content_filter.blocklist:
+                try:
+                    re.compile(pattern)
+                except re.error as e:
+                    raise ConfigError(
+                        f"Invalid pattern in `{key}.content_filter.blocklist`: {e}."
+                    )
+            args = (model_name, model, profile, settings, language, footer)
             if key == "chat":
-                return ChatConfig(model_name, model, profile, settings)
-            return TaskConfig(model_name, model, profile, settings)
+                idle_timeout = config[key].get("idle_timeout", 0)
+                if not isinstance(idle_timeout, int) or idle_timeout < 0:
+                    raise ConfigError("`chat.idle_timeout` must be 0 or more minutes.")
+                return ChatConfig(*args, content_filter, idle_timeout)
+            return TaskConfig(*args, content_filter)
+
+        history = get_section(HistoryConfig, "history", config.get("history"))
+        for pattern in history.ignore_patterns:
+            try:
+                re.compile(pattern)
+            except re.error as e:
+                raise ConfigError(f"Invalid pattern in `history.ignore_patterns`: {e}.")
+
+        ui = get_section(UiConfig, "ui", config.get("ui"))
+        if ui.max_width < 0:
+            raise ConfigError("`ui.max_width` must be 0 or more.")
+        if ui.stream_rate < 0:
+            raise ConfigError("`ui.stream_rate` must be 0 or more.")
+        env = get_section(EnvConfig, "env", config.get("env"))
 
         chat = _get_config("chat")
         task = _get_config("task")
-        return cls(models, profiles, cast(ChatConfig, chat), cast(TaskConfig, task))
+        return cls(
+            models,
+            profiles,
+            cast(ChatConfig, chat),
+            cast(TaskConfig, task),
+            history,
+            ui,
+            env,
+            warnings=warnings,
+        )
+
+
+def parse_yaml(
+    content: str, file_path: str, category: Literal["config", "template"] = "config"
+) -> Any:
+    """Parse yaml content from a file.
+
+    Syntax errors are raised as ConfigError, or TemplateError for the template
+    category, with the file path, line, column and a snippet of the lines
+    around the error.
+    """
+    error = TemplateError if category == "template" else ConfigError
+    try:
+        return yaml.safe_load(content)
+    except yaml.MarkedYAMLError as e:
+        mark = e.problem_mark or e.context_mark
+        problem = e.problem or e.context or "invalid yaml"
+        if not mark:
+            raise error(f"Invalid yaml in {file_path}: {problem}.")
+
+        lines = content.splitlines()
+        end = min(mark.line + 1, len(lines))
+        snippet = [f"{i + 1:>4} | {lines[i]}" for i in range(max(end - 3, 0), end)]
+        snippet.append(f"     | {' ' * mark.column}^")
+        raise error(
+            f"Invalid yaml in {file_path} at line {mark.line + 1}, column"
+            f" {mark.column + 1}: {problem}.\n\n```\n" + "\n".join(snippet) + "\n```",
+        )
+    except yaml.YAMLError as e:
+        raise error(f"Invalid yaml in {file_path}: {e}.")
+
+
+@dataclass
+class ConfigMigration:
+    """Upgrade of the config file content from a version to the next."""
+
+    from_version: int
+    description: str
+    migrate: Callable[[dict], None]
+
+
+# Register a migration here and bump CONFIG_VERSION when the config schema
+# changes incompatibly. Migrations are applied in order of from_version.
+_migrations: List[ConfigMigration] = []
+
+
+def migrate_config(config_file: str, content: dict) -> List[str]:
+    """Upgrade the config content to current version and save it.
+
+    The config file is backed up before it is rewritten. Returns a description
+    for each applied migration.
+    """
+    version = content.get("version", CONFIG_VERSION)
+    if not isinstance(version, int) or version > CONFIG_VERSION:
+        raise ConfigError(
+            f"Config version '{version}' is not supported. Upgrade arey or set"
+            f" `version: {CONFIG_VERSION}` in {config_file}.",
+        )
+
+    pending = sorted(
+        [m for m in _migrations if m.from_version >= version],
+        key=lambda m: m.from_version,
+    )
+    if version == CONFIG_VERSION or not pending:
+        return []
+
+    for migration in pending:
+        migration.migrate(content)
+    content["version"] = CONFIG_VERSION
+
+    backup_file = f"{config_file}.v{version}.bak"
+    shutil.copyfile(config_file, backup_file)
+    with open(config_file, "w", encoding="utf-8") as f:
+        yaml.safe_dump(content, f, sort_keys=False, allow_unicode=True)
+    return [m.description for m in pending] + [f"Backup saved to {backup_file}."]
+
+
+def find_workspace_config(start_dir: str) -> Optional[str]:
+    """Find the workspace config file for start_dir.
+
+    Looks for `.arey.yml` in start_dir, then in the root of its git repository.
+    """
+    current = os.path.abspath(start_dir)
+    config_file = os.path.join(current, WORKSPACE_CONFIG_FILE)
+    if os.path.isfile(config_file):
+        return config_file
+
+    while not os.path.exists(os.path.join(current, ".git")):
+        parent = os.path.dirname(current)
+        if parent == current:
+            return None
+        current = parent
+    config_file = os.path.join(current, WORKSPACE_CONFIG_FILE)
+    return config_file if os.path.isfile(config_file) else None
+
+
+# Sections a workspace config may set. A cloned repository must not pick the
+# commands run by /env, send prompts to another server or turn off the content
+# filter.
+WORKSPACE_SECTIONS = [
+    "version",
+    "allowed_models",
+    "models",
+    "profiles",
+    "chat",
+    "task",
+    "ui",
+]
+WORKSPACE_DENIED_SETTINGS = ["host", "headers"]
+
+
+def merge_config(content: dict, overlay: dict) -> dict:
+    """Get the config content with the workspace overlay applied.
+
+    Mappings, e.g., `models` or `chat`, are merged recursively and other values
+    are replaced. `allowed_models` in the overlay keeps only the listed models.
+    Raises ConfigError for sections or settings an overlay can't set, e.g.,
+    `env`, the Ollama `host` or `content_filter`.
+    """
+    denied = [key for key in overlay if key not in WORKSPACE_SECTIONS]
+    for section in ["chat", "task"]:
+        if "content_filter" in (overlay.get(section) or {}):
+            denied.append(f"{section}.content_filter")
+        settings = (overlay.get(section) or {}).get("settings") or {}
+        denied += [
+            f"{section}.settings.{key}"
+            for key in settings
+            if key in WORKSPACE_DENIED_SETTINGS
+        ]
+    if denied:
+        raise ConfigError(
+            f"Workspace config can't set `{', '.join(denied)}`. Set them in the"
+            " user config instead.",
+        )
+
+    def _merge(base: dict, other: dict) -> dict:
+        result = dict(base)
+        for key, value in other.items():
+            if isinstance(value, dict) and isinstance(result.get(key), dict):
+                result[key] = _merge(result[key], value)
+            else:
+                result[key] = value
+        return result
+
+    overlay = dict(overlay)
+    overlay.pop("version", None)
+    allowed = overlay.pop("allowed_models", None)
+    result = _merge(content, overlay)
+    if allowed is not None:
+        if not isinstance(allowed, list):
+            raise ConfigError("`allowed_models` must be a list of model names.")
+        unknown = [m for m in allowed if m not in result.get("models", {})]
+        if unknown:
+            raise ConfigError(f"Unknown models in `allowed_models`: {unknown}.")
+        result["models"] = {
+            k: v for k, v in result.get("models", {}).items() if k in allowed
+        }
+    return result
 
 
 def create_or_get_config_file() -> Tuple[bool, str]:
@@ -134,14 +530,49 @@ def create_or_get_config_file() -> Tuple[bool, str]:
     return (False, config_file)
 
 
+def load_config_content() -> Tuple[dict, Optional[str]]:
+    """Get the user config content with the workspace overlay applied.
+
+    The user config is migrated to the current version first. Returns the
+    content and the workspace config file, if any.
+    """
+    _, config_file = create_or_get_config_file()
+    with open(config_file, "r", encoding="utf-8") as f:
+        content = parse_yaml(f.read(), config_file) or {}
+
+    migrations = migrate_config(config_file, content)
+    if migrations:
+        console = get_console()
+        console.print(
+            f"✓ Upgraded config to version {CONFIG_VERSION}.", style="message_footer"
+        )
+        for description in migrations:
+            console.print(f"  {description}", style="message_footer")
+
+    workspace_file = find_workspace_config(os.getcwd())
+    if workspace_file:
+        with open(workspace_file, "r", encoding="utf-8") as f:
+            overlay = parse_yaml(f.read(), workspace_file) or {}
+        if not isinstance(overlay, dict):
+            raise ConfigError(f"Workspace config must be a mapping: {workspace_file}.")
+        content = merge_config(content, overlay)
+    return content, workspace_file
+
+
 def get_config() -> Config:
-    """Get the app configuration if available."""
+    """Get the app configuration if available.
+
+    A workspace config, `.arey.yml`, found for the current directory overlays
+    the user config.
+    """
     config = getattr(get_config, "config", None)
     if config:
         return config
 
-    _, config_file = create_or_get_config_file()
-    with open(config_file, "r") as f:
-        config = Config.from_dict(yaml.safe_load(f) or {})
-        setattr(get_config, "config", config)
-        return config
+    content, workspace_file = load_config_content()
+    config = Config.from_dict(content)
+    config.workspace_file = workspace_file
+    for warning in config.warnings:
+        get_console().print(f"⚠ {warning}", style="warning")
+    setattr(get_config, "config", config)
+    return config
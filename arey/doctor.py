@@ -0,0 +1,235 @@
+"""Services for the doctor command.
+
+Doctor checks the configured models and reports actionable fixes.
+"""
+import os
+import shutil
+import subprocess
+from dataclasses import dataclass
+from typing import Dict, List
+
+from arey.config import load_config_content
+from arey.error import AreyError
+from arey.platform.console import capture_stderr
+
+
+@dataclass
+class HealthCheck:
+    """Result of a health check."""
+
+    name: str
+    type: str
+    ok: bool
+    details: str
+    fix: str = ""
+
+
+def _get_model_settings(config: dict, model_key: str) -> Dict:
+    """Get the model settings from the first mode that uses the model."""
+    for mode in ["chat", "task"]:
+        section = config.get(mode) or {}
+        if section.get("model") == model_key:
+            return section.get("settings") or {}
+    return {}
+
+
+def _check_llama(name: str, model: dict) -> HealthCheck:
+    try:
+        import llama_cpp
+    except ModuleNotFoundError:
+        return HealthCheck(
+            name,
+            "llama",
+            False,
+            "Llama.cpp provider is not installed.",
+            "Run `pip install arey[llama]`.",
+        )
+
+    path = os.path.expanduser(model.get("path", ""))
+    if not path or not os.path.exists(path):
+        return HealthCheck(
+            name,
+            "llama",
+            False,
+            f"Model file not found: '{path}'.",
+            "Update `path` to a local gguf file.",
+        )
+
+    try:
+        with capture_stderr():
+            llm = llama_cpp.Llama(model_path=path, vocab_only=True, verbose=False)
+        metadata = llm.metadata
+        arch = metadata.get("general.architecture", "unknown")
+        ctx = metadata.get(f"{arch}.context_length", "unknown")
+        return HealthCheck(name, "llama", True, f"{arch}, trained context {ctx}.")
+    except Exception as e:
+        return HealthCheck(
+            name,
+            "llama",
+            False,
+            f"Failed to read model: {e}.",
+            "Ensure the file is a valid gguf model supported by llama-cpp-python.",
+        )
+
+
+def _get_ollama_tag(model_name: str) -> str:
+    """Get the model name with a tag, Ollama uses `latest` if there's none."""
+    return model_name if ":" in model_name else f"{model_name}:latest"
+
+
+def _check_ollama(name: str, model: dict, settings: dict) -> HealthCheck:
+    try:
+        from ollama import Client
+    except ModuleNotFoundError:
+        return HealthCheck(
+            name,
+            "ollama",
+            False,
+            "Ollama provider is not installed.",
+            "Run `pip install arey[ollama]`.",
+        )
+
+    host = settings.get("host", "http://localhost:11434")
+    headers = {
+        key: os.path.expandvars(str(value))
+        for key, value in (settings.get("headers") or {}).items()
+    }
+    model_name = model.get("name", "")
+    if not model_name:
+        return HealthCheck(
+            name, "ollama", False, "Model name is empty.", "Set `name` for the model."
+        )
+
+    try:
+        client = Client(host=host, headers=headers)
+        available = [
+            _get_ollama_tag(m["name"]) for m in client.list().get("models", [])
+        ]
+    except Exception as e:
+        return HealthCheck(
+            name,
+            "ollama",
+            False,
+            f"Ollama server is unreachable at {host}: {e}.",
+            "Start the server with `ollama serve` or update `host` setting.",
+        )
+
+    if _get_ollama_tag(model_name) not in available:
+        return HealthCheck(
+            name,
+            "ollama",
+            False,
+            f"Model '{model_name}' is not available at {host}.",
+            f"Run `ollama pull {model_name}`.",
+        )
+    return HealthCheck(name, "ollama", True, f"Available at {host}.")
+
+
+def check_config() -> List[HealthCheck]:
+    """Check all models in the config file.
+
+    The workspace config, if any, overlays the user config like in `arey chat`.
+    """
+    try:
+        config, _ = load_config_content()
+    except AreyError as e:
+        return [
+            HealthCheck(
+                "config",
+                "config",
+                False,
+                e.message,
+                "Fix the config file, see the config docs.",
+            )
+        ]
+
+    results: List[HealthCheck] = []
+    models = config.get("models") or {}
+    if not models:
+        results.append(
+            HealthCheck(
+                "config",
+                "config",
+                False,
+                "No models are configured.",
+                "Add a model to the `models` section.",
+            )
+        )
+
+    for key, model in models.items():
+        if model.get("type") == "ollama":
+            settings = _get_model_settings(config, key)
+            results.append(_check_ollama(key, model, settings))
+        else:
+            results.append(_check_llama(key, model))
+
+    for mode in ["chat", "task"]:
+        model_key = (config.get(mode) or {}).get("model")
+        if model_key not in models:
+            results.append(
+                HealthCheck(
+                    mode,
+                    "config",
+                    False,
+                    f"Section '{mode}' refers to unknown model '{model_key}'.",
+                    f"Set `{mode}.model` to one of the configured models.",
+                )
+            )
+    return results
+
+
+def _get_nvidia_devices() -> List[HealthCheck]:
+    if not shutil.which("nvidia-smi"):
+        return []
+
+    try:
+        output = subprocess.run(
+            [
+                "nvidia-smi",
+                "--query-gpu=name,memory.total,memory.free",
+                "--format=csv,noheader",
+            ],
+            capture_output=True,
+            text=True,
+            timeout=10,
+            check=True,
+        ).stdout
+    except (subprocess.SubprocessError, OSError) as e:
+        return [HealthCheck("nvidia-smi", "cuda", False, f"Failed to run: {e}.")]
+
+    devices = []
+    for line in output.splitlines():
+        name, total, free = [x.strip() for x in line.split(",")]
+        devices.append(HealthCheck(name, "cuda", True, f"{free} free of {total}."))
+    return devices
+
+
+def check_gpu() -> List[HealthCheck]:
+    """Check the GPU backend of llama.cpp and the available devices."""
+    try:
+        from arey.platform._llama import get_system_info, has_gpu_support
+    except ModuleNotFoundError:
+        return [
+            HealthCheck(
+                "llama.cpp",
+                "gpu",
+                False,
+                "Llama.cpp provider is not installed.",
+                "Run `pip install arey[llama]`.",
+            )
+        ]
+
+    info = get_system_info()
+    if has_gpu_support():
+        details = f"GPU offload is supported. {info}"
+        backend = HealthCheck("llama.cpp", "gpu", True, details)
+    else:
+        backend = HealthCheck(
+            "llama.cpp",
+            "gpu",
+            False,
+            f"Built without a GPU backend. {info}",
+            "Reinstall llama-cpp-python with CUDA, Metal or Vulkan enabled."
+            " See the contribute docs.",
+        )
+    return [backend] + _get_nvidia_devices()
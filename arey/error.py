@@ -1,19 +1,64 @@
 """Error routines for Arey."""
-from typing import Union, Literal
+import datetime
+import os
+import traceback
+from typing import Literal
+
+from arey.platform.assets import get_data_dir
+
+AreyErrorCategory = Literal["config", "template", "provider", "system"]
+ProviderErrorReason = Literal[
+    "missing", "network", "auth", "context_overflow", "other"
+]
 
 
 class AreyError(Exception):
     """Error in Arey execution."""
 
-    category: Union[Literal["config"], Literal["template"], Literal["system"]]
+    category: AreyErrorCategory
     message: str
 
-    def __init__(
-        self,
-        category: Union[Literal["config"], Literal["template"], Literal["system"]],
-        message: str,
-    ):
+    def __init__(self, category: AreyErrorCategory, message: str):
         """Create an instance of AreyError with category and message."""
         self.category = category
         self.message = message
         super().__init__(message)
+
+
+class ConfigError(AreyError):
+    """Invalid configuration, e.g., an unknown model or setting."""
+
+    def __init__(self, message: str):
+        """Create an instance of ConfigError with message."""
+        super().__init__("config", message)
+
+
+class TemplateError(AreyError):
+    """Invalid prompt template, chat template or conversation script."""
+
+    def __init__(self, message: str):
+        """Create an instance of TemplateError with message."""
+        super().__init__("template", message)
+
+
+class ProviderError(AreyError):
+    """Failure in a model provider.
+
+    Reason lets callers handle specific failures, e.g., a context overflow.
+    """
+
+    reason: ProviderErrorReason
+
+    def __init__(self, reason: ProviderErrorReason, message: str):
+        """Create an instance of ProviderError with reason and message."""
+        self.reason = reason
+        super().__init__("provider", message)
+
+
+def write_crash_log(error: BaseException) -> str:
+    """Append the error traceback to the crash log and get its path."""
+    log_file = os.path.join(get_data_dir(), "crash.log")
+    with open(log_file, "a", encoding="utf-8") as f:
+        f.write(f"--- {datetime.datetime.now().isoformat()}\n")
+        f.write("".join(traceback.format_exception(error)))
+    return log_file
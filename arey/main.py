@@ -1,12 +1,16 @@
 """Arey app cli entrypoint."""
 #!/usr/bin/env python
 import click
+import contextlib
 import signal
+import sys
 import datetime
+import time
 from functools import wraps
-from typing import Callable, Iterable, Optional
+from typing import TYPE_CHECKING, Callable, Iterator, Optional
 
 from rich.console import Console, Group
+from rich.constrain import Constrain
 from rich.live import Live
 from rich.markdown import Markdown
 from rich.padding import Padding
@@ -15,16 +19,30 @@ from rich.text import Text
 
 from watchfiles import watch
 
-from arey.ai import CompletionMetrics
-from arey.error import AreyError
+from arey.ai import CompletionMetrics, ModelMetrics
+from arey.config import get_config
+from arey.error import (
+    AreyError,
+    ConfigError,
+    ProviderError,
+    TemplateError,
+    write_crash_log,
+)
 from arey.platform.console import SignalContextManager, get_console
+from arey.platform.formats import get_format_names
+from arey.platform.latex import render_math
 from arey.play import PlayFile
 
+if TYPE_CHECKING:
+    from arey.chat import Chat
+
+STREAM_FRAMES_PER_SEC = 30
+
 
 def _generate_response(
     console: Console,
     output_settings: dict,
-    run: Callable[[], Iterable[str]],
+    run: Callable[[], Iterator[str]],
     get_metrics: Callable[[], Optional[CompletionMetrics]],
 ) -> None:
     stop_completion = False
@@ -36,11 +54,18 @@ def _generate_response(
     spinner = Spinner(
         text="[message_footer]Generating...", name="dots", style="message_footer"
     )
+    # Rich wraps at the current terminal width on every render
+    max_width = get_config().ui.max_width or None
+    stream_rate = get_config().ui.stream_rate
     text = Text()
     output = Group(
-        Padding(text, pad=(0, 0, 2, 0)),
+        Constrain(Padding(text, pad=(0, 0, 2, 0)), max_width),
         spinner,
     )
+    stream = output_settings.get("stream", True)
+    if not stream:
+        # Show the formatted response once it completes
+        output = Group(spinner)
 
     with SignalContextManager(signal.SIGINT, stop_completion_handler):
         with Live(
@@ -48,22 +73,40 @@ def _generate_response(
             console=console,
             transient=True,
         ):
-            for response in run():
-                if stop_completion:
-                    break
-                text.append(response)
+            # Close the stream on cancel so the model stops generating and the
+            # services can release it right away
+            with contextlib.closing(run()) as chunks:
+                for response in chunks:
+                    if stop_completion:
+                        break
+                    if not stream or not stream_rate:
+                        text.append(response)
+                        continue
+
+                    # Spread large chunks from bursty servers over a few frames
+                    step = max(stream_rate // STREAM_FRAMES_PER_SEC, 1)
+                    for index in range(0, len(response), step):
+                        if stop_completion:
+                            break
+                        text.append(response[index : index + step])
+                        if len(response) > step:
+                            time.sleep(1 / STREAM_FRAMES_PER_SEC)
 
     # Default output in markdown
     output_format = output_settings.get("format", "markdown")
     if output_format == "plain":
-        console.print(text.plain)
+        console.print(Constrain(Text(text.plain), max_width))
     else:
-        console.print(Markdown(text.plain))
+        console.print(Constrain(Markdown(render_math(text.plain)), max_width))
 
     console.print()
+    # Footer verbosity: off, minimal or full
+    footer_mode = output_settings.get("footer", "full")
     metrics = get_metrics()
     footer = "◼ Canceled." if stop_completion else "◼ Completed."
-    if metrics:
+    if metrics and footer_mode == "minimal":
+        footer += f" {metrics.completion_latency_ms / 1000:.2f}s."
+    elif metrics and footer_mode == "full":
         tokens_per_sec = (
             metrics.completion_tokens * 1000 / metrics.completion_latency_ms
         )
@@ -75,8 +118,43 @@ def _generate_response(
             f" {metrics.prompt_tokens} prompt tokens."
         )
 
+    if footer_mode != "off" or stop_completion:
+        console.print()
+        console.print(footer, style="message_footer")
     console.print()
+
+
+def _print_model_loaded(console: Console, metrics: ModelMetrics) -> None:
+    footer = f"✓ Model loaded. {metrics.init_latency_ms / 1000:.2f}s."
+    if metrics.kv_cache_bytes:
+        footer += f" {metrics.kv_cache_bytes / 2**20:.2f} MiB kv cache."
     console.print(footer, style="message_footer")
+    for warning in metrics.warnings:
+        console.print(f"⚠ {warning}", style="warning")
+
+
+def _print_instructions_loaded(console: Console, file_path: Optional[str]) -> None:
+    if file_path:
+        console.print(f"✓ Using instructions from {file_path}.", style="message_footer")
+
+
+def _print_workspace_config(console: Console) -> None:
+    workspace_file = get_config().workspace_file
+    if workspace_file:
+        console.print(
+            f"✓ Using workspace config {workspace_file}.", style="message_footer"
+        )
+
+
+def _print_chat_banner(console: Console, context_size: int) -> None:
+    """Print the chat model details, so it's clear which model responds."""
+    config = get_config().chat
+    provider = "ollama" if config.model.type == "ollama" else "llama.cpp"
+    details = [f"{config.model_name} ({provider})", f"{config.model.template} template"]
+    if context_size:
+        details.insert(1, f"{context_size} tokens context")
+    console.print(f"Welcome to arey chat! {' · '.join(details)}", markup=False)
+    console.print("Type 'q' to exit, /help for commands.")
     console.print()
 
 
@@ -88,6 +166,54 @@ def _print_logs(console: Console, verbose: bool, logs: Optional[str]) -> None:
     console.print()
 
 
+def _generate_candidates(
+    console: Console, output_settings: dict, chat: "Chat", message: str, count: int
+) -> None:
+    """Generate count responses for message and keep the one user picks."""
+    from arey.chat import (
+        get_completion_metrics,
+        keep_candidate,
+        pop_candidate,
+        stream_response,
+    )
+
+    candidates = []
+    for index in range(count):
+        console.rule(f"Candidate {index + 1} of {count}", style="message_footer")
+        console.print()
+        _generate_response(
+            console,
+            output_settings,
+            lambda: stream_response(chat, message),
+            lambda: get_completion_metrics(chat),
+        )
+        candidate = pop_candidate(chat)
+        if not candidate:
+            # Canceled, skip the remaining candidates
+            break
+        candidates.append(candidate)
+
+    if not candidates:
+        return
+    choice = 1
+    if len(candidates) > 1:
+        try:
+            choice = click.prompt(
+                "Pick a response",
+                type=click.IntRange(1, len(candidates)),
+                default=1,
+            )
+        except click.exceptions.Abort:
+            console.print()
+            console.print("◼ Discarded all candidates.", style="message_footer")
+            console.print()
+            return
+        console.print()
+    keep_candidate(chat, message, candidates[choice - 1])
+    console.print(f"✓ Kept candidate {choice}.", style="message_footer")
+    console.print()
+
+
 def error_handler(func):
     """Global error handler for Arey."""
 
@@ -103,11 +229,24 @@ def error_handler(func):
                 "<https://github.com/codito/arey/issues/new>"
             )
 
-            match e.category:
-                case "template":
+            match e:
+                case TemplateError():
                     help_text = "A template seems misconfigured. Check out the docs."
-                case "config":
+                case ConfigError():
                     help_text = "Config file seems misconfigured. Check out the docs."
+                case ProviderError(reason="missing"):
+                    help_text = "A model provider is missing. See the install docs."
+                case ProviderError(reason="network"):
+                    help_text = "Check that the model server is running and reachable."
+                case ProviderError(reason="auth"):
+                    help_text = (
+                        "Check the `host` and `headers` in the model settings, and"
+                        " that the environment variables they use are set."
+                    )
+                case ProviderError(reason="context_overflow"):
+                    help_text = "Start a new chat or use a model with larger context."
+                case ProviderError():
+                    help_text = "The model provider failed. Run with `-v` for logs."
 
             error_text = Group(
                 Markdown(f"ERROR: {e.args[0]}", style="error"),
@@ -115,6 +254,26 @@ def error_handler(func):
                 Markdown(help_text),
             )
             console.print(error_text)
+        except (click.ClickException, click.exceptions.Abort, click.exceptions.Exit):
+            raise
+        except Exception as e:
+            # Live displays restore the terminal on exit, ensure the cursor is
+            # visible if the error happened elsewhere.
+            console = get_console()
+            console.show_cursor(True)
+            log_file = write_crash_log(e)
+            console.print()
+            console.print(
+                f"ERROR: Unexpected error: {e!r}. Details are in {log_file}.",
+                style="error",
+                markup=False,
+            )
+            console.print(
+                "Please report this bug at <https://github.com/codito/arey/issues/new>"
+                " with the details.",
+                markup=False,
+            )
+            raise SystemExit(1)
 
     return wrapper
 
@@ -133,59 +292,204 @@ def common_options(func):
 
 
 @click.group()
-def main():
+@click.option(
+    "--record",
+    type=click.Path(dir_okay=False),
+    help="Record model interactions to a cassette file.",
+)
+@click.option(
+    "--replay",
+    type=click.Path(exists=True, dir_okay=False),
+    help="Replay model interactions from a cassette file.",
+)
+def main(record: Optional[str], replay: Optional[str]):
     """Arey - a simple large language model app."""
-    pass
+    from arey.platform.llm import use_cassette
+
+    if record and replay:
+        raise click.UsageError("Use only one of --record or --replay.")
+    try:
+        if record:
+            use_cassette("record", record)
+        if replay:
+            use_cassette("replay", replay)
+    except AreyError as e:
+        raise click.UsageError(e.message)
 
 
 @main.command("ask")
 @click.argument("instruction", nargs=-1)
 @click.option("-o", "--overrides-file", type=click.File())
+@click.option(
+    "-p",
+    "--profile",
+    help="Profile for completion settings. Defaults to the task profile in config.",
+)
+@click.option(
+    "--emit",
+    type=click.Choice(["text", "ndjson"]),
+    default="text",
+    help="Output format. Use ndjson for structured events on stdout.",
+)
+@click.option(
+    "--listen",
+    type=click.Path(dir_okay=False),
+    help="Unix socket path to stream ndjson events to a client.",
+)
+@click.option(
+    "--no-instructions",
+    is_flag=True,
+    default=False,
+    help="Don't add project instructions from AREY.md to the system prompt.",
+)
+@click.option(
+    "--no-stream",
+    is_flag=True,
+    default=False,
+    help="Wait for the complete response instead of streaming it.",
+)
 @error_handler
 @common_options
-def task(instruction: str, overrides_file: str, verbose: bool) -> int:
+def task(
+    instruction: str,
+    overrides_file: str,
+    profile: Optional[str],
+    emit: str,
+    listen: Optional[str],
+    no_instructions: bool,
+    no_stream: bool,
+    verbose: bool,
+) -> int:
     """Run an instruction and generate response."""
+    if emit == "ndjson" and listen:
+        raise click.UsageError("Use only one of --emit ndjson or --listen.")
+    if emit == "ndjson":
+        # Keep stdout for the events, config warnings and errors go to stderr
+        get_console().stderr = True
+
+    from arey.platform.events import EventWriter, stream_events
+    from arey.platform.events import listen as listen_events
     from arey.task import create_task, run
 
+    if emit == "ndjson":
+        task, _ = create_task(overrides_file, profile, not no_instructions)
+        events = stream_events(
+            EventWriter(sys.stdout),
+            run(task, instruction),
+            lambda: (task.result and task.result.metrics),
+            lambda: (task.result and task.result.finish_reason),
+        )
+        for _ in events:
+            pass
+        return 0
+
     console = get_console()
     console.print()
     console.print("Welcome to arey ask!")
     console.print()
 
     with console.status("[message_footer]Loading model..."):
-        task, model_metrics = create_task(overrides_file)
-        footer = f"✓ Model loaded. {model_metrics.init_latency_ms / 1000:.2f}s."
-        console.print(footer, style="message_footer")
+        task, model_metrics = create_task(overrides_file, profile, not no_instructions)
+        _print_model_loaded(console, model_metrics)
+        _print_instructions_loaded(console, task.instructions_file)
+        _print_workspace_config(console)
         console.print()
 
-    _generate_response(
-        console,
-        {},
-        lambda: run(task, instruction),
-        lambda: (task.result and task.result.metrics),
-    )
+    with contextlib.ExitStack() as stack:
+        writer: Optional[EventWriter] = None
+        if listen:
+            with console.status(f"[message_footer]Waiting for a client on {listen}"):
+                writer = stack.enter_context(listen_events(listen))
+
+        def get_response() -> Iterator[str]:
+            chunks = run(task, instruction)
+            if not writer:
+                return chunks
+            return stream_events(
+                writer,
+                chunks,
+                lambda: (task.result and task.result.metrics),
+                lambda: (task.result and task.result.finish_reason),
+            )
+
+        _generate_response(
+            console,
+            {"footer": get_config().task.footer, "stream": not no_stream},
+            get_response,
+            lambda: (task.result and task.result.metrics),
+        )
 
     _print_logs(console, verbose, task.result and task.result.logs)
     return 0
 
 
 @main.command("chat")
+@click.option(
+    "-t",
+    "--template",
+    type=click.Path(exists=True, dir_okay=False),
+    help="Chat template file with messages to seed the conversation.",
+)
+@click.option(
+    "--no-instructions",
+    is_flag=True,
+    default=False,
+    help="Don't add project instructions from AREY.md to the system prompt.",
+)
+@click.option(
+    "--no-stream",
+    is_flag=True,
+    default=False,
+    help="Wait for the complete response instead of streaming it.",
+)
 @error_handler
 @common_options
-def chat(verbose: bool) -> int:
+def chat(
+    template: Optional[str], no_instructions: bool, no_stream: bool, verbose: bool
+) -> int:
     """Chat with an AI model."""
-    from arey.chat import create_chat, get_completion_metrics, stream_response
+    from arey.chat import (
+        config,
+        create_chat,
+        expand_mentions,
+        get_candidate_count,
+        get_completion_metrics,
+        load_template,
+        model,
+        reload_model,
+        stream_response,
+    )
+    from arey.commands import (
+        get_command_names,
+        is_command,
+        is_shell_command,
+        run_command,
+        run_shell_command,
+    )
+    from arey.platform.completion import setup_completion
+    from arey.platform.history import setup_history
+    from arey.platform.idle import IdleTimer
 
     console = get_console()
-    console.print(("Welcome to arey chat!\nType 'q' to exit."))
     console.print()
 
     with console.status("[message_footer]Loading model..."):
-        chat, model_metrics = create_chat()
-        footer = f"✓ Model loaded. {model_metrics.init_latency_ms / 1000:.2f}s."
-        console.print(footer, style="message_footer")
+        chat, model_metrics = create_chat(not no_instructions)
+        _print_model_loaded(console, model_metrics)
+        _print_instructions_loaded(console, chat.context.instructions_file)
+        _print_workspace_config(console)
+        if template:
+            count = load_template(chat, template)
+            console.print(f"✓ Seeded {count} messages.", style="message_footer")
         console.print()
 
+    setup_completion(get_command_names())
+    setup_history(config.history.max_entries, config.history.ignore_patterns)
+    get_candidate_count(chat)  # fail early for an invalid chat profile
+    output_settings = {"footer": config.chat.footer, "stream": not no_stream}
+    # Free the model memory while waiting for input, it reloads on next input
+    idle_timer = IdleTimer(config.chat.idle_timeout * 60, model.free)
+    _print_chat_banner(console, model.context_size)
     console.print("How can I help you today?")
     while True:
         # Get input from user
@@ -193,6 +497,9 @@ def chat(verbose: bool) -> int:
         with console.capture() as capture:
             console.print("> ", style="message_prompt", end="")
         prompt_str = capture.get()
+        # Only local models release memory when freed
+        if config.chat.idle_timeout and model.frees_memory:
+            idle_timer.start()
         try:
             user_input = input(prompt_str)
         except KeyboardInterrupt:
@@ -207,9 +514,35 @@ def chat(verbose: bool) -> int:
             break
 
         console.print()
+        idle_timer.stop()
+        if idle_timer.fired:
+            with console.status("[message_footer]Reloading idle model..."):
+                reload_model(chat)
+            idle_timer.fired = False
+            console.print("✓ Reloaded the model.", style="message_footer")
+        if is_command(user_input):
+            run_command(console, chat, user_input)
+            continue
+        if is_shell_command(user_input):
+            run_shell_command(console, chat, user_input)
+            continue
+
+        user_input, attached, errors = expand_mentions(chat, user_input)
+        for path in attached:
+            console.print(f"✓ Attached {path}.", style="message_footer")
+        for error in errors:
+            console.print(f"⚠ {error}", style="warning")
+
+        candidate_count = get_candidate_count(chat)
+        if candidate_count > 1:
+            _generate_candidates(
+                console, output_settings, chat, user_input, candidate_count
+            )
+            continue
+
         _generate_response(
             console,
-            {},
+            output_settings,
             lambda: stream_response(chat, user_input),
             lambda: get_completion_metrics(chat),
         )
@@ -227,6 +560,102 @@ def chat(verbose: bool) -> int:
     return 0
 
 
+@main.command("run")
+@click.argument("script", type=click.Path(exists=True, dir_okay=False))
+@click.option(
+    "-o",
+    "--output",
+    type=click.Path(dir_okay=False),
+    help="Save the transcript to a markdown file.",
+)
+@click.option(
+    "--format",
+    "response_format",
+    type=click.Choice(get_format_names()),
+    help="Format of the responses. Adds instructions and constrains json output.",
+)
+@click.option(
+    "--env",
+    "attach_env",
+    is_flag=True,
+    default=False,
+    help="Attach a snapshot of the OS, shell and tool versions to the chat.",
+)
+@click.option(
+    "--no-instructions",
+    is_flag=True,
+    default=False,
+    help="Don't add project instructions from AREY.md to the system prompt.",
+)
+@click.option(
+    "--no-stream",
+    is_flag=True,
+    default=False,
+    help="Wait for the complete response instead of streaming it.",
+)
+@error_handler
+@common_options
+def run(
+    script: str,
+    output: Optional[str],
+    response_format: Optional[str],
+    attach_env: bool,
+    no_instructions: bool,
+    no_stream: bool,
+    verbose: bool,
+) -> int:
+    """Run the user turns in SCRIPT as a single chat conversation."""
+    from arey.chat import (
+        add_env_context,
+        create_chat,
+        export_transcript,
+        get_completion_metrics,
+        load_script,
+        stream_response,
+    )
+
+    turns = load_script(script)
+    console = get_console()
+    console.print()
+    console.print(f"Welcome to arey run! Playing {len(turns)} turns.")
+    console.print()
+
+    with console.status("[message_footer]Loading model..."):
+        chat, model_metrics = create_chat(not no_instructions)
+        chat.context.response_format = response_format
+        _print_model_loaded(console, model_metrics)
+        _print_instructions_loaded(console, chat.context.instructions_file)
+        _print_workspace_config(console)
+        if attach_env:
+            add_env_context(chat)
+            console.print("✓ Attached environment.", style="message_footer")
+        console.print()
+
+    for turn in turns:
+        console.print(f"> {turn}", style="message_prompt", markup=False)
+        console.print()
+        _generate_response(
+            console,
+            {"footer": get_config().chat.footer, "stream": not no_stream},
+            lambda: stream_response(chat, turn),
+            lambda: get_completion_metrics(chat),
+        )
+        _print_logs(
+            console,
+            verbose,
+            (
+                chat.messages[-1].context.logs
+                if chat.messages and chat.messages[-1].context
+                else ""
+            ),
+        )
+
+    if output:
+        export_transcript(chat, output)
+        console.print(f"✓ Saved transcript to {output}.", style="message_footer")
+    return 0
+
+
 @main.command("play")
 @click.argument("file", required=False)
 @click.option(
@@ -259,6 +688,8 @@ def play(file: str, no_watch: bool, verbose: bool) -> int:
         console.rule(current_date)
         play_file_mod = get_play_file(file_path)
         play_file_mod.model = play_file_old.model
+        for warning in play_file_mod.warnings:
+            console.print(f"⚠ {warning}", style="warning")
 
         # Compare old playfile with current and reload model if settings have
         # changed
@@ -269,8 +700,7 @@ def play(file: str, no_watch: bool, verbose: bool) -> int:
         ):
             with console.status("[message_footer]Loading model..."):
                 model_metrics = load_play_model(play_file_mod)
-                footer = f"✓ Model loaded. {model_metrics.init_latency_ms / 1000:.2f}s."
-                console.print(footer, style="message_footer")
+                _print_model_loaded(console, model_metrics)
         console.print()
         output_settings = play_file_mod.output_settings
 
@@ -297,5 +727,45 @@ def play(file: str, no_watch: bool, verbose: bool) -> int:
     return 0
 
 
-if __name__ == "__common__":
+@main.command("doctor")
+@click.option(
+    "--gpu", is_flag=True, default=False, help="Check the GPU backend and devices."
+)
+@error_handler
+@common_options
+def doctor(gpu: bool, verbose: bool) -> int:
+    """Check the configured models and suggest fixes."""
+    from rich.table import Table
+
+    from arey.doctor import check_config, check_gpu
+
+    console = get_console()
+    console.print()
+    with console.status("[message_footer]Checking models..."):
+        results = check_config()
+        if gpu:
+            results += check_gpu()
+
+    table = Table(show_lines=True)
+    table.add_column("Name")
+    table.add_column("Type")
+    table.add_column("Status")
+    table.add_column("Details")
+    table.add_column("Fix")
+    for result in results:
+        status = "[green]✓ OK[/green]" if result.ok else "[error]✗ Failed[/error]"
+        table.add_row(result.name, result.type, status, result.details, result.fix)
+    console.print(table)
+
+    failed = len([r for r in results if not r.ok])
+    footer = f"◼ {failed} problem(s) found." if failed else "◼ All good."
+    console.print()
+    console.print(footer, style="message_footer")
+    console.print()
+    if failed:
+        raise SystemExit(1)
+    return 0
+
+
+if __name__ == "__main__":
     main()
@@ -1,9 +1,12 @@
 """Llama.cpp based models."""
 import dataclasses
+import inspect
 import os
+import random
+import re
 import time
 import multiprocessing
-from typing import Iterator, cast
+from typing import Any, Dict, Iterator, List, Optional, cast
 
 import llama_cpp
 
@@ -13,8 +16,60 @@ from arey.ai import (
     CompletionModel,
     CompletionResponse,
     ModelMetrics,
+    TokenLogprob,
 )
-from arey.error import AreyError
+from arey.error import AreyError, ConfigError, ProviderError
+
+# Supported KV cache types with ggml type id and bytes per element
+KV_CACHE_TYPES: Dict[str, tuple[int, float]] = {
+    "f32": (0, 4),
+    "f16": (1, 2),
+    "q4_0": (2, 18 / 32),
+    "q4_1": (3, 20 / 32),
+    "q5_0": (6, 22 / 32),
+    "q5_1": (7, 24 / 32),
+    "q8_0": (8, 34 / 32),
+}
+
+# Recommended sampler settings of model families, the profile overrides these
+MODEL_FAMILY_PROFILES: Dict[str, dict] = {
+    "qwen": {
+        "temperature": 0.7,
+        "top_k": 20,
+        "top_p": 0.8,
+        "min_p": 0.0,
+        "repeat_penalty": 1.05,
+    },
+    "llama3": {"temperature": 0.6, "top_k": 40, "top_p": 0.9, "repeat_penalty": 1.0},
+    "mistral": {
+        "temperature": 0.3,
+        "top_k": 40,
+        "top_p": 0.95,
+        "repeat_penalty": 1.0,
+    },
+    "gemma": {
+        "temperature": 1.0,
+        "top_k": 64,
+        "top_p": 0.95,
+        "min_p": 0.0,
+        "repeat_penalty": 1.0,
+    },
+}
+
+# Samplers replaced by mirostat in llama.cpp
+MIROSTAT_EXCLUSIVE_SAMPLERS = ["top_k", "top_p", "min_p", "typical_p"]
+# Profile settings used by arey or other providers, e.g., num_predict for Ollama
+IGNORED_SETTINGS = ["n_candidates", "num_predict"]
+
+
+def has_gpu_support() -> bool:
+    """Check if llama-cpp-python is built with a GPU backend."""
+    return bool(llama_cpp.llama_supports_gpu_offload())
+
+
+def get_system_info() -> str:
+    """Get the backend and cpu features of llama-cpp-python build."""
+    return llama_cpp.llama_print_system_info().decode("utf-8").strip()
 
 
 @dataclasses.dataclass
@@ -26,9 +81,76 @@ class LlamaSettings:
     n_batch: int = 512
     n_gpu_layers: int = 0
     use_mlock: bool = False
+    use_mmap: bool = True
+    cache_type_k: str = "f16"
+    cache_type_v: str = "f16"
+    no_kv_offload: bool = False  # keep kv cache in main memory
+    logits_all: bool = False  # required for logprobs
+    lora_adapters: List[Dict] = dataclasses.field(default_factory=list)
     verbose: bool = True
 
 
+def get_model_family(metadata: Dict[str, str]) -> Optional[str]:
+    """Get the model family from gguf metadata, e.g., `qwen`.
+
+    Matches the model's base name, e.g., `Meta-Llama-3.1`, or its architecture,
+    e.g., `gemma3`. Returns None for unknown families.
+    """
+    name = metadata.get("general.basename") or metadata.get("general.name") or ""
+    name = re.sub(r"[^a-z0-9]", "", name.lower())
+    arch = metadata.get("general.architecture", "")
+    for family in MODEL_FAMILY_PROFILES:
+        if family in name or arch.startswith(family):
+            return family
+    return None
+
+
+def get_sampler_settings(settings: dict) -> dict:
+    """Validate the sampler settings in a profile for llama.cpp.
+
+    `mirostat` is accepted as an alias of `mirostat_mode` for parity with Ollama
+    profiles, and settings for other providers are dropped. Raises AreyError
+    for invalid values, samplers that are ignored with mirostat, and settings
+    the installed llama-cpp-python doesn't support, e.g., a typo.
+    """
+    result = {k: v for k, v in settings.items() if k not in IGNORED_SETTINGS}
+    if "mirostat" in result:
+        result["mirostat_mode"] = result.pop("mirostat")
+
+    for key in ["min_p", "typical_p"]:
+        value = result.get(key)
+        if value is not None and not 0 <= value <= 1:
+            raise ConfigError(f"Invalid `{key}`: {value}. Use 0.0 to 1.0.")
+
+    mirostat_mode = result.get("mirostat_mode", 0)
+    if mirostat_mode not in (0, 1, 2):
+        raise ConfigError(f"Invalid `mirostat`: {mirostat_mode}. Use 0, 1 or 2.")
+    conflicts = [k for k in MIROSTAT_EXCLUSIVE_SAMPLERS if k in result]
+    if mirostat_mode and conflicts:
+        raise ConfigError(
+            f"`{', '.join(conflicts)}` can't be used with `mirostat`. Mirostat"
+            " replaces these samplers, remove them from the profile.",
+        )
+
+    parameters = inspect.signature(llama_cpp.Llama.create_completion).parameters
+    if any(p.kind == p.VAR_KEYWORD for p in parameters.values()):
+        return result
+    # `format` is converted to a grammar
+    unsupported = [k for k in result if k not in parameters and k != "format"]
+    samplers = [k for k in unsupported if k.startswith(("dry_", "xtc_"))]
+    if samplers:
+        raise ConfigError(
+            f"`{', '.join(samplers)}` isn't supported by the installed"
+            " llama-cpp-python. Upgrade it or remove the settings from the profile.",
+        )
+    if unsupported:
+        raise ConfigError(
+            f"`{', '.join(unsupported)}` isn't supported by llama.cpp models. Remove"
+            " the settings from the profile.",
+        )
+    return result
+
+
 class LlamaBaseModel(CompletionModel):
     """Base local completion model.
 
@@ -66,14 +188,71 @@ class LlamaBaseModel(CompletionModel):
             start_time = time.perf_counter()
             self._llm = llama_cpp.Llama(
                 model_path=model_path,
-                **dataclasses.asdict(self._model_settings),
+                **self._get_llama_params(),
             )
             # self._llm.set_cache(llama_cpp.LlamaCache(2 << 33))
 
             latency_ms = round((time.perf_counter() - start_time) * 1000, 2)
-            self._metrics = ModelMetrics(init_latency_ms=latency_ms)
+            self._metrics = ModelMetrics(
+                init_latency_ms=latency_ms,
+                kv_cache_bytes=self._get_kv_cache_size(self._llm),
+                model_family=get_model_family(self._llm.metadata),
+                chat_template=self._llm.metadata.get("tokenizer.chat_template", ""),
+                warnings=self._get_warnings(),
+            )
         return self._llm
 
+    def _get_warnings(self) -> List[str]:
+        warnings = []
+        if self._model_settings.n_gpu_layers and not has_gpu_support():
+            warnings.append(
+                "`n_gpu_layers` is set but llama-cpp-python is built without GPU"
+                " support. Model runs on CPU. Run `arey doctor --gpu` for details."
+            )
+        return warnings
+
+    def _get_kv_cache_size(self, llm: llama_cpp.Llama) -> Optional[int]:
+        """Estimate the kv cache memory from model metadata."""
+        metadata = llm.metadata
+        arch = metadata.get("general.architecture")
+        try:
+            n_layer = int(metadata[f"{arch}.block_count"])
+            n_embd = int(metadata[f"{arch}.embedding_length"])
+            n_head = int(metadata[f"{arch}.attention.head_count"])
+            n_head_kv = int(metadata.get(f"{arch}.attention.head_count_kv", n_head))
+        except (KeyError, ValueError):
+            return None
+
+        n_elements = llm.n_ctx() * n_layer * (n_embd // n_head) * n_head_kv
+        k_size = KV_CACHE_TYPES[self._model_settings.cache_type_k][1]
+        v_size = KV_CACHE_TYPES[self._model_settings.cache_type_v][1]
+        return int(n_elements * (k_size + v_size))
+
+    def _get_llama_params(self) -> dict:
+        params = dataclasses.asdict(self._model_settings)
+
+        for key, param in [("cache_type_k", "type_k"), ("cache_type_v", "type_v")]:
+            cache_type = params.pop(key)
+            if cache_type not in KV_CACHE_TYPES:
+                raise ConfigError(
+                    f"Invalid `{key}`: {cache_type}. Supported types are"
+                    f" {', '.join(KV_CACHE_TYPES.keys())}.",
+                )
+            params[param] = KV_CACHE_TYPES[cache_type][0]
+        params["offload_kqv"] = not params.pop("no_kv_offload")
+
+        # llama-cpp-python supports a single adapter
+        adapters = params.pop("lora_adapters")
+        if len(adapters) > 1:
+            raise ConfigError("Only one adapter is supported in `lora_adapters`.")
+        for adapter in adapters:
+            lora_path = os.path.expanduser(adapter.get("path", ""))
+            if not os.path.exists(lora_path):
+                raise ConfigError(f"Invalid LoRA adapter path: {lora_path}.")
+            params["lora_path"] = lora_path
+            params["lora_scale"] = float(adapter.get("scale", 1.0))
+        return params
+
     def load(self, text: str):
         """Load a model into memory."""
         model = self._get_model()
@@ -95,7 +274,44 @@ class LlamaBaseModel(CompletionModel):
             "top_p": 0.1,
             "repeat_penalty": 1.176,
             "echo": False,
-        } | settings
+        }
+        family = get_model_family(model.metadata)
+        completion_settings |= MODEL_FAMILY_PROFILES.get(family or "", {})
+        completion_settings |= get_sampler_settings(settings)
+        logprobs = completion_settings.pop("logprobs", None)
+        if logprobs:
+            if not self._model_settings.logits_all:
+                raise ConfigError(
+                    "Set `logits_all: true` in model settings to capture logprobs.",
+                )
+            completion_settings["logprobs"] = 1 if logprobs is True else logprobs
+
+        # Pick a seed if none is configured so the turn can be reproduced
+        if completion_settings.get("seed") is None:
+            completion_settings["seed"] = random.randint(0, 2**31 - 1)
+        sampler = self._get_sampler(completion_settings)
+
+        # Constrain the output to valid json with a grammar
+        response_format = completion_settings.pop("format", None)
+        if response_format == "json":
+            completion_settings["grammar"] = llama_cpp.LlamaGrammar.from_string(
+                llama_cpp.llama_grammar.JSON_GBNF, verbose=False
+            )
+        elif response_format:
+            raise ConfigError(f"Invalid `format`: {response_format}. Use json.")
+
+        # Prompt tokens matching the evaluated tokens are reused from kv cache
+        prompt_tokens = model.tokenize(text.encode("utf-8"))
+        if len(prompt_tokens) >= model.n_ctx():
+            raise ProviderError(
+                "context_overflow",
+                f"Prompt has {len(prompt_tokens)} tokens, more than the context size"
+                f" of {model.n_ctx()}. Shorten it or increase `n_ctx` in model"
+                " settings.",
+            )
+        cached_token_count = llama_cpp.Llama.longest_token_prefix(
+            model._input_ids.tolist(), prompt_tokens
+        )
         output = cast(
             Iterator[llama_cpp.CompletionChunk],
             model.create_completion(
@@ -104,7 +320,7 @@ class LlamaBaseModel(CompletionModel):
             ),
         )
 
-        prompt_token_count = self.count_tokens(text)
+        prompt_token_count = len(prompt_tokens)
         prompt_eval_latency = -1
         for chunk in output:
             chunk_text = chunk["choices"][0]["text"]
@@ -125,8 +341,35 @@ class LlamaBaseModel(CompletionModel):
                     token_count,
                     1,
                     round(latency * 1000, 2),
+                    cached_token_count,
                 ),
+                logprobs=self._get_logprobs(chunk["choices"][0].get("logprobs")),
+                sampler=sampler,
+            )
+            sampler = None
+
+    def _get_sampler(self, completion_settings: dict) -> Dict[str, Any]:
+        """Get the sampler settings and context parameters for a completion."""
+        sampler = {
+            k: v
+            for k, v in completion_settings.items()
+            if k not in ("prompt", "stop", "echo", "logprobs")
+        }
+        for key in ("n_ctx", "n_batch", "n_threads", "n_gpu_layers"):
+            sampler[key] = getattr(self._model_settings, key)
+        sampler["cache_type_k"] = self._model_settings.cache_type_k
+        sampler["cache_type_v"] = self._model_settings.cache_type_v
+        return sampler
+
+    def _get_logprobs(self, data: Optional[dict]) -> Optional[List[TokenLogprob]]:
+        if not data:
+            return None
+        return [
+            TokenLogprob(token, logprob or 0.0, top or {})
+            for token, logprob, top in zip(
+                data["tokens"], data["token_logprobs"], data["top_logprobs"]
             )
+        ]
 
     def count_tokens(self, text: str) -> int:
         """Get the token count for given text."""
@@ -134,8 +377,15 @@ class LlamaBaseModel(CompletionModel):
         return len(model.tokenize(text.encode("utf-8")))
 
     def free(self) -> None:
+        """Free the model and its context memory."""
         if self._llm:
-            del self._llm
+            self._llm.close()
+            self._llm = None
+
+    @property
+    def frees_memory(self) -> bool:
+        """Free releases the model memory, it's loaded again on next use."""
+        return True
 
     @staticmethod
     def validate_config(config: dict) -> bool:
@@ -1,9 +1,12 @@
 """Ollama based completion model."""
+import contextlib
 import dataclasses
+import os
 import time
 from typing import Any, Iterator, cast, Mapping
 
-from ollama import Client, Options
+import httpx
+from ollama import Client, Options, ResponseError
 
 from arey.ai import (
     ChatMessage,
@@ -12,6 +15,7 @@ from arey.ai import (
     CompletionResponse,
     ModelMetrics,
 )
+from arey.error import ProviderError
 
 
 @dataclasses.dataclass
@@ -19,6 +23,7 @@ class OllamaSettings:
     """Core model settings."""
 
     host: str = "http://localhost:11434"
+    headers: dict[str, str] = dataclasses.field(default_factory=dict)  # per request
 
 
 class OllamaBaseModel(CompletionModel):
@@ -47,14 +52,43 @@ class OllamaBaseModel(CompletionModel):
     def metrics(self) -> ModelMetrics:
         return self._metrics
 
+    @contextlib.contextmanager
+    def _handle_errors(self) -> Iterator[None]:
+        """Raise server failures as provider errors."""
+        try:
+            yield
+        except httpx.ConnectError as e:
+            raise ProviderError(
+                "network",
+                f"Can't connect to Ollama at {self._model_settings.host}: {e}.",
+            ) from e
+        except ResponseError as e:
+            # Authenticated gateways in front of Ollama reject missing tokens
+            if e.status_code in (401, 403):
+                raise ProviderError(
+                    "auth",
+                    f"Ollama at {self._model_settings.host} denied access to model"
+                    f" '{self._model_name}' ({e.status_code}): {e.error}.",
+                ) from e
+            raise ProviderError(
+                "other", f"Ollama failed for model '{self._model_name}': {e.error}."
+            ) from e
+
     def load(self, text: str) -> None:
-        self.client = Client(**dataclasses.asdict(self._model_settings))
+        # Header values may refer to environment variables, e.g., ${API_KEY}
+        headers = {
+            key: os.path.expandvars(str(value))
+            for key, value in self._model_settings.headers.items()
+        }
+        self.client = Client(host=self._model_settings.host, headers=headers)
         # response = self.client.show(self._model_name)
         # self._model_ctx_size = response["parameters"]["num_ctx"]
 
-        response = cast(
-            Mapping[str, Any], self.client.generate(model=self._model_name, prompt=text)
-        )
+        with self._handle_errors():
+            response = cast(
+                Mapping[str, Any],
+                self.client.generate(model=self._model_name, prompt=text),
+            )
 
         load_latency_ms = round(response.get("load_duration", 0) / 1000, 2)
         self._metrics = ModelMetrics(init_latency_ms=load_latency_ms)
@@ -72,13 +106,18 @@ class OllamaBaseModel(CompletionModel):
             "top_k": 40,
             "top_p": 0.1,
             "repeat_penalty": 1.176,
-            "raw": True,  # do not preserve context in the server
+            "raw": True,  # prompt is already formatted, skip the model's template
         } | settings
+        # Profiles may set max_tokens, ollama calls it num_predict
+        if "max_tokens" in settings and "num_predict" not in settings:
+            completion_settings["num_predict"] = settings["max_tokens"]
         output = cast(
             Iterator[Mapping[str, Any]],
             self.client.generate(
                 model=self._model_name,
                 prompt=text,
+                raw=completion_settings["raw"],
+                format=completion_settings.get("format", ""),
                 options=self._get_options(completion_settings),
                 stream=True,
             ),
@@ -86,35 +125,45 @@ class OllamaBaseModel(CompletionModel):
 
         prompt_token_count = 0
         prompt_eval_latency = -1
-        token_count = 0
-        for chunk in output:
-            chunk_text = chunk["response"]
-            if chunk["done"]:
-                prompt_token_count = chunk.get("prompt_eval_count", 0)
-                token_count = chunk.get("eval_count", 0)
-
-            current_time = time.perf_counter()
-            latency = current_time - prev_time
-            prev_time = current_time
-            if prompt_eval_latency == -1:
-                prompt_eval_latency = round(latency * 1000, 2)
-
-            yield CompletionResponse(
-                text=chunk_text,
-                finish_reason="stop" if chunk["done"] else None,
-                metrics=CompletionMetrics(
-                    prompt_token_count,
-                    prompt_eval_latency,
-                    token_count,
-                    1,
-                    round(latency * 1000, 2),
-                ),
-            )
+        streamed_token_count = 0
+        with self._handle_errors():
+            for chunk in output:
+                chunk_text = chunk["response"]
+                # Ollama reports usage in the last chunk, count a token per chunk
+                # until then so a canceled response has an estimate
+                token_count = 1
+                if chunk["done"]:
+                    prompt_token_count = chunk.get("prompt_eval_count", 0)
+                    token_count = max(
+                        chunk.get("eval_count", 0) - streamed_token_count, 0
+                    )
+                streamed_token_count += token_count
+
+                current_time = time.perf_counter()
+                latency = current_time - prev_time
+                prev_time = current_time
+                if prompt_eval_latency == -1:
+                    prompt_eval_latency = round(latency * 1000, 2)
+
+                yield CompletionResponse(
+                    text=chunk_text,
+                    finish_reason="stop" if chunk["done"] else None,
+                    metrics=CompletionMetrics(
+                        prompt_token_count,
+                        prompt_eval_latency,
+                        token_count,
+                        1,
+                        round(latency * 1000, 2),
+                    ),
+                )
 
     def count_tokens(self, text: str) -> int:
         """Get the token count for given text."""
         return 0
 
+    def free(self) -> None:
+        """Nothing to free, the Ollama server manages the model memory."""
+
     @staticmethod
     def validate_config(config: dict) -> bool:
         assert config["name"], "Model name is required for Ollama models."
@@ -123,27 +172,9 @@ class OllamaBaseModel(CompletionModel):
     def _get_options(self, data: dict[str, Any]) -> Options:
         """Convert completion settings to ollama options.
 
-        # runtime options
-        num_keep: int
-        seed: int
-        num_predict: int
-        top_k: int
-        top_p: float
-        tfs_z: float
-        typical_p: float
-        repeat_last_n: int
-        temperature: float
-        repeat_penalty: float
-        presence_penalty: float
-        frequency_penalty: float
-        mirostat: int
-        mirostat_tau: float
-        mirostat_eta: float
-        penalize_newline: bool
-        stop: Sequence[str]
+        Settings that aren't ollama options, e.g., `n_candidates` or `raw`, are
+        dropped. Options is a TypedDict in older ollama versions and a pydantic
+        model in newer ones.
         """
-        result = Options()
-        for key, val in data.items():
-            if key in result:
-                result[key] = val
-        return result
+        names = getattr(Options, "model_fields", None) or Options.__annotations__
+        return Options(**{key: val for key, val in data.items() if key in names})
@@ -48,6 +48,14 @@ def get_config_dir():
     return config_dir
 
 
+def get_data_dir():
+    """Get arey data dir."""
+    base_dir = os.environ.get("XDG_DATA_HOME")
+    data_dir = os.path.join(base_dir, "arey") if base_dir else DEFAULT_DATA_DIR
+    _make_dir(data_dir)
+    return data_dir
+
+
 def get_default_config() -> str:
     """Get default configuration template."""
     config_file = get_asset_path("config.yml")
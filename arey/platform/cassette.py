@@ -0,0 +1,216 @@
+"""Record and replay model interactions.
+
+A cassette is a json file with all completion requests and responses of a
+session. Replaying a cassette serves the recorded responses back without
+loading a model. Useful for deterministic tests and offline demos.
+"""
+import json
+import os
+from dataclasses import asdict
+from typing import Dict, Iterator, List, Optional
+
+from arey.ai import (
+    ChatMessage,
+    CompletionMetrics,
+    CompletionModel,
+    CompletionResponse,
+    ModelMetrics,
+    TokenLogprob,
+)
+from arey.error import AreyError
+
+CASSETTE_VERSION = 2
+
+
+class Cassette:
+    """Recorded model interactions backed by a file.
+
+    Interactions are recorded per model, e.g., the chat and task models, so
+    each replays its own responses and context size.
+    """
+
+    path: str
+    models: Dict[str, dict]
+
+    def __init__(self, path: str) -> None:
+        """Create a cassette for the given file path."""
+        self.path = path
+        self.models = {}
+        self._positions: Dict[str, int] = {}
+
+    @classmethod
+    def load(cls, path: str) -> "Cassette":
+        """Load a recorded cassette from file."""
+        if not os.path.exists(path):
+            raise AreyError("system", f"Cassette file not found: {path}.")
+        with open(path, "r", encoding="utf-8") as f:
+            content = json.load(f)
+        if content.get("version") != CASSETTE_VERSION:
+            raise AreyError("system", f"Unsupported cassette version: {path}.")
+
+        cassette = cls(path)
+        cassette.models = content.get("models", {})
+        return cassette
+
+    def save(self) -> None:
+        """Write the cassette to file."""
+        content = {"version": CASSETTE_VERSION, "models": self.models}
+        with open(self.path, "w", encoding="utf-8") as f:
+            json.dump(content, f, indent=2)
+
+    def _get_recording(self, model: str) -> dict:
+        return self.models.setdefault(model, {"context_size": 0, "interactions": []})
+
+    def get_context_size(self, model: str) -> int:
+        """Get the recorded context size of a model, 0 if it isn't recorded."""
+        return self.models.get(model, {}).get("context_size", 0)
+
+    def set_context_size(self, model: str, context_size: int) -> None:
+        """Set the context size of a model."""
+        self._get_recording(model)["context_size"] = context_size
+
+    def record(
+        self, model: str, prompt: str | list, settings: dict, responses: list
+    ) -> None:
+        """Add an interaction for a model and persist the cassette."""
+        self._get_recording(model)["interactions"].append(
+            {"prompt": prompt, "settings": settings, "responses": responses}
+        )
+        self.save()
+
+    def play(self, model: str, prompt: str | list) -> List[dict]:
+        """Get the recorded responses for the next interaction of a model."""
+        interactions = self.models.get(model, {}).get("interactions", [])
+        position = self._positions.get(model, 0)
+        if position >= len(interactions):
+            raise AreyError(
+                "system",
+                f"Cassette has no more recorded interactions for model '{model}'.",
+            )
+
+        interaction = interactions[position]
+        if interaction["prompt"] != prompt:
+            raise AreyError(
+                "system",
+                f"Prompt doesn't match recorded interaction #{position + 1} for model"
+                f" '{model}'.",
+            )
+        self._positions[model] = position + 1
+        return interaction["responses"]
+
+
+def _to_prompt(text: str | list[ChatMessage]) -> str | list:
+    if isinstance(text, str):
+        return text
+    return [{"role": m.sender.role(), "text": m.text} for m in text]
+
+
+class RecordingModel(CompletionModel):
+    """A completion model that records all interactions to a cassette."""
+
+    def __init__(self, model: CompletionModel, cassette: Cassette, name: str) -> None:
+        """Create a recording wrapper over the given model, recorded as name."""
+        self._model = model
+        self._cassette = cassette
+        self._name = name
+
+    @property
+    def context_size(self) -> int:
+        """Get context size for the model."""
+        return self._model.context_size
+
+    @property
+    def metrics(self) -> ModelMetrics:
+        """Get metrics for the model."""
+        return self._model.metrics
+
+    def load(self, text: str):
+        """Load the underlying model."""
+        self._model.load(text)
+        self._cassette.set_context_size(self._name, self._model.context_size)
+
+    def complete(
+        self, text: str | list[ChatMessage], settings: dict = {}
+    ) -> Iterator[CompletionResponse]:
+        """Get a completion and record it."""
+        responses = []
+        try:
+            for chunk in self._model.complete(text, settings):
+                responses.append(asdict(chunk))
+                yield chunk
+        finally:
+            self._cassette.record(self._name, _to_prompt(text), settings, responses)
+
+    def count_tokens(self, text: str) -> int:
+        """Get the token count for given text."""
+        return self._model.count_tokens(text)
+
+    def free(self) -> None:
+        """Free the underlying model."""
+        self._model.free()
+
+    @property
+    def frees_memory(self) -> bool:
+        """Whether the underlying model releases memory on free."""
+        return self._model.frees_memory
+
+    @staticmethod
+    def validate_config(config: dict) -> bool:
+        """Validate the model configuration."""
+        return True
+
+
+class ReplayModel(CompletionModel):
+    """A completion model that serves responses from a cassette."""
+
+    _metrics: Optional[ModelMetrics] = None
+
+    def __init__(self, cassette: Cassette, name: str) -> None:
+        """Create a replay model for the responses recorded as name."""
+        self._cassette = cassette
+        self._name = name
+
+    @property
+    def context_size(self) -> int:
+        """Get context size for the recorded model."""
+        return self._cassette.get_context_size(self._name)
+
+    @property
+    def metrics(self) -> ModelMetrics:
+        """Get metrics for the model."""
+        return self._metrics or ModelMetrics(init_latency_ms=0)
+
+    def load(self, text: str):
+        """Load the model. No-op for replay."""
+        self._metrics = ModelMetrics(init_latency_ms=0)
+
+    def complete(
+        self, text: str | list[ChatMessage], settings: dict = {}
+    ) -> Iterator[CompletionResponse]:
+        """Replay a recorded completion."""
+        for response in self._cassette.play(self._name, _to_prompt(text)):
+            logprobs = response.get("logprobs")
+            yield CompletionResponse(
+                text=response["text"],
+                finish_reason=response["finish_reason"],
+                metrics=CompletionMetrics(**response["metrics"]),
+                logprobs=[TokenLogprob(**lp) for lp in logprobs] if logprobs else None,
+                sampler=response.get("sampler"),
+            )
+
+    def count_tokens(self, text: str) -> int:
+        """Get an approximate token count for given text.
+
+        Tokenizer isn't available during replay. We use a rough estimate of
+        four characters per token.
+        """
+        return len(text) // 4
+
+    def free(self) -> None:
+        """Free any resources for the model."""
+        pass
+
+    @staticmethod
+    def validate_config(config: dict) -> bool:
+        """Validate the model configuration."""
+        return True
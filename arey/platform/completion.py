@@ -0,0 +1,72 @@
+"""Tab completion for the chat input.
+
+Completes command names after `/`, and file paths after `@` and in `!` shell
+commands. Requires the `readline` module, which isn't available on Windows.
+"""
+import glob
+import os
+from typing import List, Optional
+
+
+def complete_path(prefix: str) -> List[str]:
+    """Get the file system paths starting with prefix.
+
+    Home directory marker `~` is preserved in the results. Hidden files are
+    included only if the prefix's file name starts with a `.`.
+    """
+    expanded = os.path.expanduser(prefix)
+    home = os.path.expanduser("~")
+    show_hidden = os.path.basename(prefix).startswith(".")
+
+    results = []
+    for path in sorted(glob.glob(glob.escape(expanded) + "*")):
+        if os.path.basename(path).startswith(".") and not show_hidden:
+            continue
+        if os.path.isdir(path):
+            path += os.sep
+        if prefix.startswith("~") and path.startswith(home):
+            path = "~" + path[len(home) :]
+        results.append(path)
+    return results
+
+
+class ChatCompleter:
+    """Completer for the chat input."""
+
+    def __init__(self, commands: List[str]) -> None:
+        """Create a completer with the given command names."""
+        self._commands = sorted(commands)
+        self._matches: List[str] = []
+
+    def get_matches(self, line: str, word: str, begin: int) -> List[str]:
+        """Get completions for the word starting at begin in line."""
+        if line.startswith("/") and begin == 0:
+            return [f"/{c} " for c in self._commands if f"/{c}".startswith(word)]
+        if word.startswith("@"):
+            return ["@" + p for p in complete_path(word[1:])]
+        if line.startswith("!"):
+            if begin == 0:
+                return ["!" + p for p in complete_path(word[1:])]
+            return complete_path(word)
+        return []
+
+    def complete(self, text: str, state: int) -> Optional[str]:
+        """Complete the text; readline calls this with increasing state."""
+        import readline
+
+        if state == 0:
+            line = readline.get_line_buffer()
+            self._matches = self.get_matches(line, text, readline.get_begidx())
+        return self._matches[state] if state < len(self._matches) else None
+
+
+def setup_completion(commands: List[str]) -> None:
+    """Enable tab completion for the chat input, if supported."""
+    try:
+        import readline
+    except ImportError:
+        return
+
+    readline.set_completer(ChatCompleter(commands).complete)
+    readline.set_completer_delims(" \t\n")
+    readline.parse_and_bind("tab: complete")
@@ -17,6 +17,7 @@ theme = Theme(
         "message_user": "blue",
         "message_ai": "bright_white",
         "message_footer": "dim white",
+        "warning": "yellow",
         "error": "bold red",
     }
 )
@@ -0,0 +1,44 @@
+"""Content filter for model responses.
+
+A filter level adds safety instructions to the system prompt. Responses
+matching a blocklist pattern are replaced with a notice after completion.
+"""
+import re
+from typing import List, Optional, Tuple
+
+SAFETY_INSTRUCTIONS = {
+    "standard": (
+        "Do not produce hateful, harassing, sexually explicit or violent content,"
+        " or instructions that could cause harm."
+    ),
+    "strict": (
+        "Do not produce hateful, harassing, sexually explicit or violent content,"
+        " or instructions that could cause harm. Politely decline requests for"
+        " such content and avoid mature topics."
+    ),
+}
+FILTERED_NOTICE = "_Response removed by the content filter._"
+
+
+def get_safety_instructions(level: str) -> Optional[str]:
+    """Get the safety instructions for a filter level, None if it's off."""
+    return SAFETY_INSTRUCTIONS.get(level)
+
+
+def find_blocked(text: str, blocklist: List[str]) -> Optional[str]:
+    """Get the first blocklist pattern matching text, if any."""
+    for pattern in blocklist:
+        if re.search(pattern, text, re.IGNORECASE):
+            return pattern
+    return None
+
+
+def filter_response(text: str, blocklist: List[str]) -> Tuple[str, str]:
+    """Get the response to keep and a log entry if it matched the blocklist."""
+    pattern = find_blocked(text, blocklist)
+    if not pattern:
+        return text, ""
+    return (
+        FILTERED_NOTICE,
+        f"Content filter: response matched `{pattern}`, replaced with a notice.\n",
+    )
@@ -0,0 +1,130 @@
+"""Extract text from documents attached to a chat.
+
+Html and docx files are parsed with the standard library. Pdf files require the
+optional `pypdf` package, install with `pip install arey[docs]`. Other files
+are read as plain text.
+
+Size limits apply to html, docx and pdf files since they are parsed in memory.
+"""
+import os
+import re
+import zipfile
+from html.parser import HTMLParser
+from typing import List
+from xml.etree import ElementTree
+
+from arey.error import AreyError, ProviderError
+
+MAX_DOCUMENT_BYTES = {
+    ".pdf": 20 * 2**20,
+    ".docx": 10 * 2**20,
+    ".html": 5 * 2**20,
+    ".htm": 5 * 2**20,
+}
+
+_WORD_NAMESPACE = "{http://schemas.openxmlformats.org/wordprocessingml/2006/main}"
+
+
+class _HtmlTextParser(HTMLParser):
+    """Collect the visible text of an html document."""
+
+    _skip_tags = {"script", "style", "head", "noscript", "svg"}
+    _block_tags = {"p", "div", "br", "li", "tr", "h1", "h2", "h3", "h4", "h5", "h6"}
+
+    def __init__(self) -> None:
+        super().__init__()
+        self.parts: List[str] = []
+        self._skip_depth = 0
+
+    def handle_starttag(self, tag, attrs):
+        if tag in self._skip_tags:
+            self._skip_depth += 1
+        elif tag in self._block_tags:
+            self.parts.append("\n")
+
+    def handle_endtag(self, tag):
+        if tag in self._skip_tags and self._skip_depth > 0:
+            self._skip_depth -= 1
+
+    def handle_data(self, data):
+        if not self._skip_depth:
+            self.parts.append(data)
+
+
+def _from_html(path: str) -> str:
+    with open(path, "r", encoding="utf-8", errors="replace") as f:
+        content = f.read()
+    parser = _HtmlTextParser()
+    try:
+        parser.feed(content)
+        parser.close()
+    except Exception:
+        # Malformed markup, share the raw content instead
+        return content
+    text = "".join(parser.parts)
+    return re.sub(r"\n\s*\n+", "\n\n", re.sub(r"[ \t]+", " ", text)).strip()
+
+
+def _from_docx(path: str) -> str:
+    with zipfile.ZipFile(path) as archive:
+        root = ElementTree.fromstring(archive.read("word/document.xml"))
+    paragraphs = []
+    for paragraph in root.iter(f"{_WORD_NAMESPACE}p"):
+        paragraphs.append(
+            "".join(node.text or "" for node in paragraph.iter(f"{_WORD_NAMESPACE}t"))
+        )
+    return "\n".join(paragraphs).strip()
+
+
+def _from_pdf(path: str) -> str:
+    try:
+        from pypdf import PdfReader
+    except ModuleNotFoundError as e:
+        raise ProviderError(
+            "missing",
+            "Pdf support is not installed. Install it with `pip install arey[docs]`.",
+        ) from e
+
+    reader = PdfReader(path)
+    return "\n\n".join(page.extract_text() or "" for page in reader.pages).strip()
+
+
+def extract_text(path: str, max_chars: int) -> str:
+    """Get up to max_chars of the text content of a document.
+
+    Raises AreyError if the file is too large, binary or can't be read.
+    """
+    extension = os.path.splitext(path)[1].lower()
+    readers = {
+        ".pdf": _from_pdf,
+        ".docx": _from_docx,
+        ".html": _from_html,
+        ".htm": _from_html,
+    }
+    try:
+        if extension not in readers:
+            with open(path, "r", encoding="utf-8") as f:
+                content = f.read(max_chars)
+            if "\x00" in content:
+                raise AreyError("system", f"File '{path}' is binary, not text.")
+            return content
+        size = os.path.getsize(path)
+    except UnicodeDecodeError as e:
+        raise AreyError("system", f"File '{path}' isn't UTF-8 text.") from e
+    except OSError as e:
+        raise AreyError("system", f"Failed to read '{path}': {e}.") from e
+
+    max_bytes = MAX_DOCUMENT_BYTES[extension]
+    if size > max_bytes:
+        raise AreyError(
+            "system",
+            f"File '{path}' is too large: {size / 2**20:.1f} MiB. Limit for"
+            f" '{extension}' files is {max_bytes / 2**20:.0f} MiB.",
+        )
+
+    try:
+        return readers[extension](path)[:max_chars]
+    except AreyError:
+        raise
+    except Exception as e:
+        raise AreyError("system", f"Failed to read '{path}': {e}.") from e
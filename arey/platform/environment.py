@@ -0,0 +1,51 @@
+"""Snapshot of the user's environment.
+
+Shared as context for troubleshooting prompts, e.g., why a build fails.
+"""
+import os
+import platform
+import shlex
+import subprocess
+from typing import List
+
+COMMAND_TIMEOUT_SECONDS = 5
+
+
+def _get_version(command: str) -> str:
+    """Get the first line of command output, empty if the command fails.
+
+    The command runs without a shell, e.g., pipes aren't supported.
+    """
+    try:
+        result = subprocess.run(
+            shlex.split(command),
+            capture_output=True,
+            text=True,
+            errors="replace",
+            timeout=COMMAND_TIMEOUT_SECONDS,
+        )
+    except (OSError, ValueError, subprocess.TimeoutExpired):
+        return ""
+    if result.returncode != 0:
+        return ""
+    output = (result.stdout or result.stderr).strip()
+    return output.splitlines()[0] if output else ""
+
+
+def get_env_snapshot(variables: List[str], commands: List[str]) -> str:
+    """Get the OS, shell, set environment variables and tool versions.
+
+    Commands that fail, e.g., for tools that aren't installed, are skipped.
+    """
+    shell = os.environ.get("SHELL") or os.environ.get("COMSPEC") or "unknown"
+    lines = [
+        f"OS: {platform.platform()}",
+        f"Shell: {shell}",
+        f"Working directory: {os.getcwd()}",
+    ]
+    lines += [f"{name}={os.environ[name]}" for name in variables if name in os.environ]
+    for command in commands:
+        version = _get_version(command)
+        if version:
+            lines.append(f"{command}: {version}")
+    return "\n".join(lines)
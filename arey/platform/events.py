@@ -0,0 +1,77 @@
+"""Structured completion events for other processes.
+
+Events are written as newline delimited json (ndjson). Each line is an object
+with an `event` key: `chunk`, `metrics` or `done`.
+"""
+import json
+import os
+import socket
+from contextlib import contextmanager
+from dataclasses import asdict
+from typing import Callable, Generator, Iterable, Iterator, Optional, TextIO
+
+from arey.ai import CompletionMetrics
+from arey.error import AreyError
+
+
+class EventWriter:
+    """Write completion events as ndjson."""
+
+    def __init__(self, out: TextIO) -> None:
+        """Create an event writer for the given stream."""
+        self._out = out
+
+    def _write(self, event: str, data: dict) -> None:
+        self._out.write(json.dumps({"event": event, **data}) + "\n")
+        self._out.flush()
+
+    def chunk(self, text: str) -> None:
+        """Write a completion chunk."""
+        self._write("chunk", {"text": text})
+
+    def metrics(self, metrics: CompletionMetrics) -> None:
+        """Write the completion metrics."""
+        self._write("metrics", asdict(metrics))
+
+    def done(self, finish_reason: Optional[str]) -> None:
+        """Write the end of completion."""
+        self._write("done", {"finish_reason": finish_reason})
+
+
+def stream_events(
+    writer: EventWriter,
+    chunks: Iterable[str],
+    get_metrics: Callable[[], Optional[CompletionMetrics]],
+    get_finish_reason: Callable[[], Optional[str]],
+) -> Iterator[str]:
+    """Write events for a completion while passing the chunks through."""
+    for chunk in chunks:
+        if chunk:
+            writer.chunk(chunk)
+        yield chunk
+
+    metrics = get_metrics()
+    if metrics:
+        writer.metrics(metrics)
+    writer.done(get_finish_reason())
+
+
+@contextmanager
+def listen(path: str) -> Generator[EventWriter, None, None]:
+    """Wait for a client on a unix socket and write events to it."""
+    if not hasattr(socket, "AF_UNIX"):
+        raise AreyError("system", "Unix sockets are not supported on this platform.")
+    if os.path.exists(path):
+        raise AreyError("system", f"Socket path already exists: {path}.")
+
+    server = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+    try:
+        server.bind(path)
+        server.listen(1)
+        conn, _ = server.accept()
+        with conn, conn.makefile("w", encoding="utf-8") as out:
+            yield EventWriter(out)
+    finally:
+        server.close()
+        if os.path.exists(path):
+            os.unlink(path)
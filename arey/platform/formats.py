@@ -0,0 +1,37 @@
+"""Response format presets.
+
+A preset adds format instructions to the system prompt. Models that support
+structured output are also constrained to the format, e.g., json.
+"""
+from typing import Dict, List, Literal, Optional, get_args
+
+ResponseFormat = Literal["json", "markdown", "plain", "list"]
+
+FORMAT_INSTRUCTIONS: Dict[str, str] = {
+    "json": (
+        "Respond only with valid JSON. Don't wrap it in a code block or add any"
+        " other text."
+    ),
+    "markdown": "Format the response as markdown, with headings and lists if useful.",
+    "plain": "Respond in plain text without any markdown formatting.",
+    "list": "Respond with a concise bulleted list, one point per line.",
+}
+
+
+def get_format_names() -> List[str]:
+    """Get the names of all format presets."""
+    return list(get_args(ResponseFormat))
+
+
+def get_format_instructions(name: Optional[str]) -> Optional[str]:
+    """Get the instructions for a format preset, None if there's no preset."""
+    return FORMAT_INSTRUCTIONS.get(name or "")
+
+
+def get_format_settings(name: Optional[str]) -> dict:
+    """Get the completion settings that constrain the output to a format.
+
+    Only json is constrained; llama.cpp models use a grammar and Ollama models
+    use its json mode.
+    """
+    return {"format": "json"} if name == "json" else {}
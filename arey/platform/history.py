@@ -0,0 +1,54 @@
+"""Persistent input history for the chat.
+
+History is saved in the data directory when the chat exits. Entries matching
+an ignore pattern and consecutive duplicates are dropped. Requires the
+`readline` module, which isn't available on Windows.
+"""
+import atexit
+import os
+import re
+from typing import List
+
+from arey.platform.assets import get_data_dir
+
+
+def filter_history(
+    entries: List[str], max_entries: int, ignore_patterns: List[str]
+) -> List[str]:
+    """Get the recent entries without ignored and consecutive duplicates."""
+    patterns = [re.compile(p) for p in ignore_patterns]
+    result: List[str] = []
+    for entry in entries:
+        if not entry.strip() or any(p.search(entry) for p in patterns):
+            continue
+        if result and result[-1] == entry:
+            continue
+        result.append(entry)
+    return result[-max_entries:] if max_entries > 0 else []
+
+
+def setup_history(max_entries: int, ignore_patterns: List[str]) -> None:
+    """Load the chat input history and save it on exit, if supported."""
+    try:
+        import readline
+    except ImportError:
+        return
+
+    history_file = os.path.join(get_data_dir(), "chat_history")
+    if os.path.exists(history_file):
+        try:
+            readline.read_history_file(history_file)
+        except OSError:
+            pass
+
+    def _save() -> None:
+        entries = [
+            readline.get_history_item(i)
+            for i in range(1, readline.get_current_history_length() + 1)
+        ]
+        readline.clear_history()
+        for entry in filter_history(entries, max_entries, ignore_patterns):
+            readline.add_history(entry)
+        readline.write_history_file(history_file)
+
+    atexit.register(_save)
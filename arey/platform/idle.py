@@ -0,0 +1,40 @@
+"""Run an action after a period of inactivity, e.g., free an idle model."""
+import threading
+from typing import Callable, Optional
+
+
+class IdleTimer:
+    """Run a callback once when the timer isn't restarted within the timeout.
+
+    The callback runs in a background thread. `stop` waits for a running
+    callback, so it's safe to use the resources it frees afterwards. `fired`
+    stays set until the caller clears it, e.g., after reloading the resources.
+    """
+
+    def __init__(self, timeout_seconds: float, callback: Callable[[], None]) -> None:
+        """Create a timer for callback, call `start` to begin the countdown."""
+        self._timeout_seconds = timeout_seconds
+        self._callback = callback
+        self._timer: Optional[threading.Timer] = None
+        self._lock = threading.Lock()
+        self.fired = False  # callback ran, cleared by the caller
+
+    def start(self) -> None:
+        """Start or restart the countdown."""
+        self.stop()
+        self._timer = threading.Timer(self._timeout_seconds, self._run)
+        self._timer.daemon = True
+        self._timer.start()
+
+    def stop(self) -> None:
+        """Cancel the countdown, or wait for the callback if it's running."""
+        if self._timer:
+            self._timer.cancel()
+            self._timer = None
+        with self._lock:
+            pass
+
+    def _run(self) -> None:
+        with self._lock:
+            self._callback()
+            self.fired = True
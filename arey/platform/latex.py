@@ -0,0 +1,125 @@
+"""Render simple LaTeX math in model responses with unicode.
+
+Terminals can't typeset math. Inline math like `$x^2 \leq \alpha$` or
+`\(x_1\)` is converted to `x² ≤ α` on a best effort basis. Expressions with
+unsupported commands are left unchanged. Code spans and blocks are skipped.
+"""
+import re
+
+_SYMBOLS = {
+    "alpha": "α",
+    "beta": "β",
+    "gamma": "γ",
+    "delta": "δ",
+    "epsilon": "ε",
+    "theta": "θ",
+    "lambda": "λ",
+    "mu": "μ",
+    "pi": "π",
+    "sigma": "σ",
+    "tau": "τ",
+    "phi": "φ",
+    "omega": "ω",
+    "Delta": "Δ",
+    "Sigma": "Σ",
+    "Omega": "Ω",
+    "times": "×",
+    "cdot": "·",
+    "div": "÷",
+    "pm": "±",
+    "leq": "≤",
+    "le": "≤",
+    "geq": "≥",
+    "ge": "≥",
+    "neq": "≠",
+    "ne": "≠",
+    "approx": "≈",
+    "equiv": "≡",
+    "infty": "∞",
+    "sum": "∑",
+    "prod": "∏",
+    "int": "∫",
+    "partial": "∂",
+    "nabla": "∇",
+    "sqrt": "√",
+    "in": "∈",
+    "notin": "∉",
+    "subset": "⊂",
+    "cup": "∪",
+    "cap": "∩",
+    "forall": "∀",
+    "exists": "∃",
+    "rightarrow": "→",
+    "to": "→",
+    "leftarrow": "←",
+    "Rightarrow": "⇒",
+    "iff": "⇔",
+    "ldots": "…",
+    "dots": "…",
+}
+_SUPERSCRIPTS = str.maketrans("0123456789+-=()ni", "⁰¹²³⁴⁵⁶⁷⁸⁹⁺⁻⁼⁽⁾ⁿⁱ")
+_SUBSCRIPTS = str.maketrans("0123456789+-=()aeijox", "₀₁₂₃₄₅₆₇₈₉₊₋₌₍₎ₐₑᵢⱼₒₓ")
+
+# Inline math: $...$ without surrounding spaces (so $5 and $10 is kept), or \(...\)
+_INLINE_MATH = re.compile(
+    r"(?<![\\$\w])\$(?=\S)([^$\n]+?)(?<=\S)\$(?![\w$])|\\\((.+?)\\\)"
+)
+_CODE = re.compile(r"(```.*?```|`[^`\n]*`)", re.DOTALL)
+
+
+class _Unsupported(Exception):
+    pass
+
+
+def _script(value: str, table: dict) -> str:
+    result = value.translate(table)
+    if any(ord(c) < 128 and c != " " for c in result):
+        raise _Unsupported()
+    return result
+
+
+def _convert(expr: str) -> str:
+    expr = re.sub(r"\\frac\{([^{}]*)\}\{([^{}]*)\}", r"\1/\2", expr)
+    expr = re.sub(r"\\(?:mathrm|text|mathbf)\{([^{}]*)\}", r"\1", expr)
+
+    def _symbol(match: re.Match) -> str:
+        name = match.group(1)
+        if name not in _SYMBOLS:
+            raise _Unsupported()
+        return _SYMBOLS[name]
+
+    expr = re.sub(
+        r"\^(?:\{([^{}]*)\}|(\w))",
+        lambda m: _script(m.group(1) or m.group(2), _SUPERSCRIPTS),
+        expr,
+    )
+    expr = re.sub(
+        r"_(?:\{([^{}]*)\}|(\w))",
+        lambda m: _script(m.group(1) or m.group(2), _SUBSCRIPTS),
+        expr,
+    )
+    # Symbols after scripts, so `x^{\alpha}` isn't rendered as `xα`
+    expr = re.sub(r"\\([A-Za-z]+)", _symbol, expr)
+    if re.search(r"[\\{^_]", expr):
+        raise _Unsupported()
+    return expr.replace("{", "").replace("}", "")
+
+
+def _replace(match: re.Match) -> str:
+    expr = match.group(1) or match.group(2)
+    if not re.search(r"[\\^_]", expr):
+        # Likely prices or plain text, e.g., "$5 or $10"
+        return match.group(0)
+    try:
+        return _convert(expr)
+    except _Unsupported:
+        return match.group(0)
+
+
+def render_math(text: str) -> str:
+    """Replace inline LaTeX math in markdown text with unicode."""
+    parts = _CODE.split(text)
+    return "".join(
+        part if index % 2 else _INLINE_MATH.sub(_replace, part)
+        for index, part in enumerate(parts)
+    )
@@ -1,21 +1,68 @@
 """Large language model abstraction."""
+from typing import Literal, Optional, Type
 
 from arey.ai import CompletionModel
-from arey.platform._llama import LlamaBaseModel
-from arey.platform._ollama import OllamaBaseModel
+from arey.error import ProviderError
+from arey.platform.cassette import Cassette, RecordingModel, ReplayModel
+
+_cassette: Optional[Cassette] = None
+_cassette_mode: Optional[Literal["record", "replay"]] = None
+
+
+def use_cassette(mode: Literal["record", "replay"], path: str) -> None:
+    """Record or replay all model interactions with a cassette file."""
+    global _cassette, _cassette_mode
+    _cassette = Cassette(path) if mode == "record" else Cassette.load(path)
+    _cassette_mode = mode
+
+
+def _get_provider(model_type: Optional[str]) -> Type[CompletionModel]:
+    """Get the completion model class for a model type.
+
+    Providers are optional dependencies. We import them only when used.
+    """
+    extra = "ollama" if model_type == "ollama" else "llama"
+    try:
+        if model_type == "ollama":
+            from arey.platform._ollama import OllamaBaseModel
+
+            return OllamaBaseModel
+
+        from arey.platform._llama import LlamaBaseModel
+
+        return LlamaBaseModel
+    except ModuleNotFoundError as e:
+        raise ProviderError(
+            "missing",
+            f"Provider for '{extra}' models is not installed. Install it with"
+            f" `pip install arey[{extra}]`.",
+        ) from e
 
 
 def get_completion_llm(model_config: dict, settings: dict) -> CompletionModel:
     """Get a completion AI model."""
-    model_name = model_config["name"]
-    model_path = model_config["path"]
+    # Interactions are recorded per model, by its name or path
+    name = model_config.get("name") or model_config.get("path", "")
+    if _cassette and _cassette_mode == "replay":
+        return ReplayModel(_cassette, name)
+
+    provider = _get_provider(model_config["type"])
+    model: CompletionModel
     if model_config["type"] == "ollama":
-        return OllamaBaseModel(model_name, settings)
-    return LlamaBaseModel(model_path, settings)
+        model = provider(model_config["name"], settings)
+    else:
+        model = provider(model_config["path"], settings)
+
+    if _cassette and _cassette_mode == "record":
+        return RecordingModel(model, _cassette, name)
+    return model
 
 
 def validate_config(model_config: dict) -> bool:
-    """Validate the model configuration."""
-    if model_config["type"] == "ollama":
-        return OllamaBaseModel.validate_config(model_config)
-    return LlamaBaseModel.validate_config(model_config)
+    """Validate the model configuration.
+
+    Skipped in replay mode, the provider and model aren't needed.
+    """
+    if _cassette_mode == "replay":
+        return True
+    return _get_provider(model_config["type"]).validate_config(model_config)
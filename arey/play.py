@@ -3,18 +3,18 @@
 Play reads the model details from a markdown file, and the prompt. It will watch
 the file for any changes and run a completion for the prompt.
 """
-from dataclasses import dataclass
+from dataclasses import dataclass, field
 import os
 import tempfile
 import frontmatter
 from functools import lru_cache
 from arey.ai import CompletionModel, CompletionMetrics, ModelMetrics, combine_metrics
-from arey.config import ModelConfig, get_config
-from arey.error import AreyError
+from arey.config import ModelConfig, get_config, get_profile
+from arey.error import AreyError, ConfigError
 from arey.platform.assets import get_asset_path
 from arey.platform.llm import get_completion_llm
 from arey.platform.console import capture_stderr
-from typing import Dict, Optional, Iterator, cast
+from typing import Dict, List, Optional, Iterator, cast
 
 
 config = get_config()
@@ -46,6 +46,7 @@ class PlayFile:
 
     model: Optional[CompletionModel] = None
     result: Optional[PlayResult] = None
+    warnings: List[str] = field(default_factory=list)  # e.g., unknown settings
 
 
 @lru_cache(maxsize=1)
@@ -67,16 +68,42 @@ def _create_file_if_not_exists(play_file_path: str) -> str:
         return file.name
 
 
+def _get_completion_profile(profile: str | dict | None, warnings: List[str]) -> dict:
+    """Resolve the completion profile from play file.
+
+    A profile can be the name of a profile in config, or a mapping of completion
+    settings. The mapping may refer to a named profile with `name` and override
+    some of its settings. Unknown settings are added to warnings.
+    """
+    if not profile:
+        return {}
+
+    overrides = {"name": profile} if isinstance(profile, str) else dict(profile)
+    profile_name = overrides.pop("name", None)
+    overrides = dict(get_profile("play file", overrides, warnings))
+    if not profile_name:
+        return overrides
+
+    if profile_name not in config.profiles:
+        raise ConfigError(f"Play file refers to an unknown profile: '{profile_name}'.")
+    return dict(config.profiles[profile_name]) | overrides
+
+
 def get_play_file(file_path: str) -> PlayFile:
     """Evaluate the provided play file and generate a response."""
     play_file_path = _create_file_if_not_exists(file_path)
     with open(play_file_path, "r", encoding="utf-8") as f:
         play_file = frontmatter.load(f)
 
-    # FIXME validate settings
-    model_config = config.models[cast(str, play_file.metadata["model"])]
+    model_name = cast(str, play_file.metadata.get("model", ""))
+    if model_name not in config.models:
+        raise ConfigError(f"Play file must have a valid `model` entry: '{model_name}'.")
+    model_config = config.models[model_name]
     model_settings: dict = cast(dict, play_file.metadata.get("settings", {}))
-    completion_profile: dict = cast(dict, play_file.metadata.get("profile", {}))
+    warnings: List[str] = []
+    completion_profile = _get_completion_profile(
+        play_file.metadata.get("profile"), warnings
+    )
     output_settings: dict = cast(dict, play_file.metadata.get("output", {}))
     return PlayFile(
         file_path=play_file_path,
@@ -85,6 +112,7 @@ def get_play_file(file_path: str) -> PlayFile:
         prompt=play_file.content,
         completion_profile=completion_profile,
         output_settings=output_settings,
+        warnings=warnings,
     )
 
 
@@ -1,19 +1,90 @@
 """Create a abstract class for chat prompts."""
 import os
+import platform
 from dataclasses import dataclass, field
+from datetime import date
 from functools import lru_cache
 from string import Template
-from typing import Dict, List, Literal
+from typing import Dict, List, Literal, Optional, Tuple
 
-import yaml
 from arey.ai import SenderTypeLiteral
 
 from arey.platform.assets import get_asset_dir
-from arey.error import AreyError
+from arey.config import parse_yaml
+from arey.error import AreyError, TemplateError
 
 SYSTEM_TOKENS = set(["message_text", "chat_history", "user_query"])
 
 
+def get_variables(model_name: str) -> Dict[str, str]:
+    """Get the variables available in custom token values.
+
+    E.g., `prompt_prefix: Today is $today.` Variables are resolved for every
+    prompt, so date and working directory stay current in long sessions.
+    """
+    return {
+        "today": date.today().isoformat(),
+        "os": platform.system(),
+        "cwd": os.getcwd(),
+        "model_name": model_name,
+    }
+
+
+INSTRUCTION_FILES = ["AREY.md", os.path.join(".arey", "instructions.md")]
+MAX_INSTRUCTION_CHARS = 16 * 1024
+
+
+def find_instructions(start_dir: str) -> Optional[Tuple[str, str]]:
+    """Find the project instructions file nearest to start_dir.
+
+    Looks for `AREY.md` or `.arey/instructions.md` in start_dir and its
+    parents. Returns the file path and content, truncated to
+    MAX_INSTRUCTION_CHARS.
+    """
+    current = os.path.abspath(start_dir)
+    while True:
+        for name in INSTRUCTION_FILES:
+            file_path = os.path.join(current, name)
+            if os.path.isfile(file_path):
+                with open(file_path, "r", encoding="utf-8", errors="replace") as f:
+                    content = f.read(MAX_INSTRUCTION_CHARS + 1)
+                if len(content) > MAX_INSTRUCTION_CHARS:
+                    content = content[:MAX_INSTRUCTION_CHARS] + "\n[truncated]"
+                return file_path, content.strip()
+
+        parent = os.path.dirname(current)
+        if parent == current:
+            return None
+        current = parent
+
+
+def get_prefix_tokens(
+    tokens: Dict[str, str],
+    language: Optional[str] = None,
+    instructions: Optional[str] = None,
+    safety: Optional[str] = None,
+) -> Dict[str, str]:
+    """Get token overrides that extend the `prompt_prefix` in tokens.
+
+    An instruction to respond in the given language is appended to the prefix,
+    followed by the safety instructions and the project instructions.
+    """
+    if not language and not instructions and not safety:
+        return {}
+    prefix = tokens.get("prompt_prefix", "")
+    if language:
+        prefix = f"{prefix} Always respond in {language}.".lstrip()
+    if safety:
+        prefix = f"{prefix} {safety}".lstrip()
+    if instructions:
+        prefix = f"{prefix}\n\n{instructions}".lstrip()
+    return {"prompt_prefix": prefix}
+
+
+def _resolve(tokens: Dict[str, str], context: Dict[str, str]) -> Dict[str, str]:
+    return {k: Template(str(v)).safe_substitute(context) for k, v in tokens.items()}
+
+
 @dataclass
 class Prompt:
     """An extensible prompt with dynamic template provided in a YML file.
@@ -29,14 +100,12 @@ class Prompt:
     message_formats: Dict[str, str] = field(default_factory=dict)  # role: format
 
     @classmethod
-    def create(cls, yml: str) -> "Prompt":
+    def create(cls, yml: str, file_path: str = "<prompt>") -> "Prompt":
         """Create a prompt from yml file."""
-        content = yaml.safe_load(yml) or {}
+        content = parse_yaml(yml, file_path, "template") or {}
         name = content.get("name", "")
         if not name:
-            raise AreyError(
-                "template", "`name` element in the prompt template is " "required."
-            )
+            raise TemplateError("`name` element in the prompt template is " "required.")
 
         tokens = content.get("tokens", {})
         system_tokens = tokens.get("system", [])
@@ -46,8 +115,7 @@ class Prompt:
 
         prompts = content.get("prompts", {})
         if not prompts.get("chat") or not prompts.get("task"):
-            raise AreyError(
-                "template",
+            raise TemplateError(
                 "`prompts` element in the prompt template is required."
                 " It must define `chat` and `task` prompt formats.",
             )
@@ -59,9 +127,7 @@ class Prompt:
             or not roles.get("user")
             or not roles.get("system")
         ):
-            raise AreyError(
-                "template", "`roles` element in the prompt template is required."
-            )
+            raise TemplateError("`roles` element in the prompt template is required.")
         message_formats = {
             "assistant": roles.get("assistant").get("message"),
             "user": roles.get("user").get("message"),
@@ -73,20 +139,16 @@ class Prompt:
         )
 
     @classmethod
-    def create_overrides(cls, yml: str) -> "Prompt":
+    def create_overrides(cls, yml: str, file_path: str = "<prompt>") -> "Prompt":
         """Create an override prompt. They are merged with the base prompt."""
-        content = yaml.safe_load(yml) or {}
+        content = parse_yaml(yml, file_path, "template") or {}
         name = content.get("name", "")
         if not name:
-            raise AreyError(
-                "template", "`name` element in the prompt template is required."
-            )
+            raise TemplateError("`name` element in the prompt template is required.")
 
         prompt_type = content.get("type", "")
         if not prompt_type:
-            raise AreyError(
-                "template", "`type` element in the prompt template is required."
-            )
+            raise TemplateError("`type` element in the prompt template is required.")
 
         # Overrides are only supported for custom_tokens currently
         tokens = content.get("tokens", {})
@@ -94,9 +156,18 @@ class Prompt:
 
         return cls(name, custom_tokens=custom_tokens)
 
-    def get(self, task: Literal["chat", "task"], context: Dict[str, str]) -> str:
+    def get(
+        self,
+        task: Literal["chat", "task"],
+        context: Dict[str, str],
+        token_overrides: Dict[str, str] = {},
+    ) -> str:
         """Get a prompt with tokens resolved from the context."""
-        merged_context = {**context, **self.custom_tokens}
+        merged_context = (
+            context
+            | _resolve(self.custom_tokens, context)
+            | _resolve(token_overrides, context)
+        )
         return Template(self.prompts[task]).substitute(merged_context)
 
     def get_message(
@@ -106,7 +177,11 @@ class Prompt:
         token_overrides: Dict[str, str] = {},
     ) -> str:
         """Get a chat message for given role and text."""
-        merged_context = {"message_text": text} | self.custom_tokens | token_overrides
+        merged_context = (
+            {"message_text": text}
+            | _resolve(self.custom_tokens, token_overrides)
+            | _resolve(token_overrides, token_overrides)
+        )
         return Template(self.message_formats[role]).substitute(merged_context)
 
 
@@ -121,8 +196,10 @@ def _get_oob_prompts() -> Dict[str, Prompt]:
 
         try:
             with open(os.path.join(dir_path, file_path), "r") as f:
-                prompt = Prompt.create(f.read())
+                prompt = Prompt.create(f.read(), file_path)
                 result[prompt.name] = prompt
+        except AreyError:
+            raise
         except Exception as err:
             print(f"Error parsing: {file_path}. Error: {err.args[0]}")
             raise
@@ -139,6 +216,53 @@ def has_prompt(template_name: str) -> bool:
     return template_name in oob_prompts
 
 
+# Role markers of common chat templates, e.g., in a gguf model file
+TEMPLATE_MARKERS = [
+    "<|im_start|>",
+    "<|start_header_id|>",
+    "<|user|>",
+    "<start_of_turn>",
+    "[INST]",
+    "### Instruction",
+]
+
+
+def get_template_marker(chat_template: str) -> Optional[str]:
+    """Get the first known role marker in a chat template, if any."""
+    return next((m for m in TEMPLATE_MARKERS if m in chat_template), None)
+
+
+def check_chat_template(prompt: Prompt, chat_template: Optional[str]) -> Optional[str]:
+    """Get a warning if the prompt template doesn't match the model's template.
+
+    chat_template is the template in the model file; None skips the check.
+    Templates are compared by their role markers, e.g., `<|im_start|>`.
+    """
+    if chat_template is None:
+        return None
+    if not chat_template:
+        return (
+            f"Model file has no chat template. Check that the `{prompt.name}`"
+            " template matches the model card if responses look garbled."
+        )
+
+    marker = get_template_marker(chat_template)
+    if not marker or marker in prompt.prompts["chat"]:
+        return None
+    matches = [
+        name for name, p in _get_oob_prompts().items() if marker in p.prompts["chat"]
+    ]
+    suggestion = (
+        f" Set `template` to one of {', '.join(sorted(matches))} in the model config."
+        if matches
+        else ""
+    )
+    return (
+        f"Model's chat template uses `{marker}`, but the `{prompt.name}` template"
+        f" doesn't.{suggestion}"
+    )
+
+
 def get_prompt(template_name: str) -> Prompt:
     """Create an out-of-box prompt from template name."""
     oob_prompts = _get_oob_prompts()
@@ -149,7 +273,9 @@ def get_prompt_overrides(prompt_file_path: str) -> Prompt:
     """Create a prompt with overrides from a file."""
     try:
         with open(prompt_file_path, "r") as f:
-            return Prompt.create_overrides(f.read())
+            return Prompt.create_overrides(f.read(), prompt_file_path)
+    except AreyError:
+        raise
     except Exception as err:
         print(f"Error parsing: {prompt_file_path}. Error: {err.args[0]}")
         raise
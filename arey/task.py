@@ -4,14 +4,23 @@ A task is stateless execution of a single instruction.
 """
 
 import os
-from dataclasses import dataclass, field
+from dataclasses import dataclass, field, replace
 from typing import Iterator, Optional, Tuple
 
 from arey.ai import CompletionMetrics, CompletionModel, ModelMetrics, combine_metrics
 from arey.config import get_config
+from arey.error import ConfigError
 from arey.platform.console import capture_stderr
+from arey.platform.content_filter import filter_response, get_safety_instructions
 from arey.platform.llm import get_completion_llm
-from arey.prompt import get_prompt, get_prompt_overrides
+from arey.prompt import (
+    check_chat_template,
+    find_instructions,
+    get_prefix_tokens,
+    get_prompt,
+    get_prompt_overrides,
+    get_variables,
+)
 
 config = get_config()
 model_config = config.task.model
@@ -40,45 +49,102 @@ class Task:
     """A task is a stateless script invocation with a prompt."""
 
     prompt_overrides: dict = field(default_factory=dict)
+    completion_settings: dict = field(default_factory=dict)
+    instructions_file: Optional[str] = None  # project instructions, e.g., AREY.md
     result: Optional[TaskResult] = None
 
 
-def create_task(prompt_file: Optional[str]) -> Tuple[Task, ModelMetrics]:
-    """Create a task with given prompt file."""
+def create_task(
+    prompt_file: Optional[str],
+    profile: Optional[str] = None,
+    use_instructions: bool = True,
+) -> Tuple[Task, ModelMetrics]:
+    """Create a task with given prompt file.
+
+    Completion settings are from the task profile in config, unless another
+    profile name is provided. Project instructions, e.g., `AREY.md`, found from
+    the current directory are added to the system prompt unless
+    use_instructions is False.
+    """
+    settings = completion_settings
+    if profile:
+        if profile not in config.profiles:
+            raise ConfigError(
+                f"Profile '{profile}' is not defined. Available profiles:"
+                f" {', '.join(config.profiles)}.",
+            )
+        settings = config.profiles[profile]
+
     token_overrides = (
         get_prompt_overrides(prompt_file).custom_tokens
         if prompt_file and os.path.exists(prompt_file)
         else {}
     )
-    system_prompt = prompt_model.get_message("system", "", token_overrides)
+    found = find_instructions(os.getcwd()) if use_instructions else None
+    token_overrides = token_overrides | get_prefix_tokens(
+        prompt_model.custom_tokens | token_overrides,
+        config.task.language,
+        found[1] if found else None,
+        get_safety_instructions(config.task.content_filter.level),
+    )
+    system_prompt = prompt_model.get_message(
+        "system", "", get_variables(config.task.model_name) | token_overrides
+    )
     with capture_stderr():
         model.load(system_prompt)
-    task = Task()
-    return task, model.metrics
+    task = Task(
+        prompt_overrides=token_overrides,
+        completion_settings=dict(settings),
+        instructions_file=found[0] if found else None,
+    )
+    metrics = model.metrics
+    warning = check_chat_template(prompt_model, metrics.chat_template)
+    if warning:
+        metrics = replace(metrics, warnings=metrics.warnings + [warning])
+    return task, metrics
 
 
 def run(task: Task, user_input: str) -> Iterator[str]:
     """Run a task with user query."""
-    context = {
+    context = get_variables(config.task.model_name) | {
         "user_query": user_input,
         "chat_history": "",
     }
-    prompt = prompt_model.get("task", context)
+    prompt = prompt_model.get("task", context, task.prompt_overrides)
 
     ai_msg_text = ""
     usage_series = []
     finish_reason = ""
-    with capture_stderr() as stderr:
-        for chunk in model.complete(prompt, {"stop": prompt_model.stop_words}):
-            ai_msg_text += chunk.text
-            finish_reason = chunk.finish_reason
-            usage_series.append(chunk.metrics)
-            yield chunk.text
-
-    task.result = TaskResult(
-        response=ai_msg_text,
-        metrics=combine_metrics(usage_series),
-        finish_reason=finish_reason,
-        logs=stderr.getvalue(),
-    )
-    model.free()
+    settings = task.completion_settings | {"stop": prompt_model.stop_words}
+    # Hold the chunks back until the response is checked against the blocklist
+    blocklist = config.task.content_filter.blocklist
+
+    def _get_result(logs: str) -> TaskResult:
+        text, filter_log = filter_response(ai_msg_text, blocklist)
+        return TaskResult(
+            response=text,
+            metrics=combine_metrics(usage_series),
+            finish_reason=finish_reason,
+            logs=logs + filter_log,
+        )
+
+    try:
+        with capture_stderr() as stderr:
+            try:
+                for chunk in model.complete(prompt, settings):
+                    ai_msg_text += chunk.text
+                    finish_reason = chunk.finish_reason
+                    usage_series.append(chunk.metrics)
+                    yield "" if blocklist else chunk.text
+            except GeneratorExit:
+                # Canceled, keep the partial response and its token usage
+                finish_reason = "canceled"
+                task.result = _get_result(stderr.getvalue())
+                raise
+
+        task.result = _get_result(stderr.getvalue())
+        if blocklist:
+            yield task.result.response
+    finally:
+        # Free the model even if the response is canceled
+        model.free()
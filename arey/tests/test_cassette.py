@@ -0,0 +1,99 @@
+"""Tests for recording and replaying model interactions."""
+from typing import Iterator
+
+import pytest
+
+from arey.ai import (
+    CompletionMetrics,
+    CompletionModel,
+    CompletionResponse,
+    ModelMetrics,
+)
+from arey.error import AreyError
+from arey.platform.cassette import Cassette, RecordingModel, ReplayModel
+
+
+class FakeModel(CompletionModel):
+    @property
+    def context_size(self) -> int:
+        return 2048
+
+    @property
+    def metrics(self) -> ModelMetrics:
+        return ModelMetrics(init_latency_ms=1)
+
+    def load(self, text: str):
+        pass
+
+    def complete(self, text, settings={}) -> Iterator[CompletionResponse]:
+        for word in ["Hello", " world"]:
+            yield CompletionResponse(
+                text=word,
+                finish_reason=None,
+                metrics=CompletionMetrics(3, 1.0, 1, 1, 2.0),
+            )
+
+    def count_tokens(self, text: str) -> int:
+        return len(text.split())
+
+    def free(self) -> None:
+        pass
+
+    @staticmethod
+    def validate_config(config: dict) -> bool:
+        return True
+
+
+@pytest.fixture
+def cassette_path(tmp_path):
+    return str(tmp_path / "session.json")
+
+
+def test_recording_model_saves_interactions(cassette_path):
+    model = RecordingModel(FakeModel(), Cassette(cassette_path), "chat-model")
+    model.load("")
+
+    response = "".join(c.text for c in model.complete("Hi", {"stop": ["\n"]}))
+
+    cassette = Cassette.load(cassette_path)
+    interactions = cassette.models["chat-model"]["interactions"]
+    assert response == "Hello world"
+    assert cassette.get_context_size("chat-model") == 2048
+    assert len(interactions) == 1
+    assert interactions[0]["prompt"] == "Hi"
+    assert interactions[0]["settings"] == {"stop": ["\n"]}
+
+
+def test_replay_model_serves_recorded_responses(cassette_path):
+    model = RecordingModel(FakeModel(), Cassette(cassette_path), "chat-model")
+    model.load("")
+    recorded = list(model.complete("Hi", {}))
+
+    replay = ReplayModel(Cassette.load(cassette_path), "chat-model")
+    replay.load("")
+    replayed = list(replay.complete("Hi", {}))
+
+    assert replayed == recorded
+    assert replay.context_size == 2048
+
+
+def test_replay_model_serves_responses_per_model(cassette_path):
+    cassette = Cassette(cassette_path)
+    list(RecordingModel(FakeModel(), cassette, "chat-model").complete("Hi", {}))
+    list(RecordingModel(FakeModel(), cassette, "task-model").complete("Sum", {}))
+    replayed = Cassette.load(cassette_path)
+
+    task = list(ReplayModel(replayed, "task-model").complete("Sum", {}))
+    chat = list(ReplayModel(replayed, "chat-model").complete("Hi", {}))
+
+    assert task == chat
+    assert ReplayModel(replayed, "task-model").context_size == 0
+
+
+def test_replay_model_throws_for_mismatched_prompt(cassette_path):
+    model = RecordingModel(FakeModel(), Cassette(cassette_path), "chat-model")
+    list(model.complete("Hi", {}))
+    replay = ReplayModel(Cassette.load(cassette_path), "chat-model")
+
+    with pytest.raises(AreyError):
+        list(replay.complete("Bye", {}))
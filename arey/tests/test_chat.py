@@ -0,0 +1,101 @@
+"""Tests for the chat services."""
+import importlib
+
+import pytest
+from pytest_mock import MockerFixture
+
+from arey.ai import SenderType
+
+
+@pytest.fixture
+def chat_module(mocker: MockerFixture):
+    config = mocker.MagicMock()
+    config.chat.model.template = "chatml"
+    mocker.patch("arey.config.get_config", return_value=config)
+    mocker.patch("arey.platform.llm.get_completion_llm")
+    # Reload so the module level config and model use the mocks, even if
+    # another test imported the module first
+    return importlib.reload(importlib.import_module("arey.chat"))
+
+
+def _add(chat_module, chat, sender: SenderType, text: str, hidden: bool = False):
+    chat.messages.append(
+        chat_module.Message(
+            text=text, sender=sender, timestamp=0, context=None, hidden=hidden
+        )
+    )
+
+
+def test_undo_last_turn_removes_attached_context(chat_module):
+    chat = chat_module.Chat()
+    _add(chat_module, chat, SenderType.USER, "Hi")
+    _add(chat_module, chat, SenderType.ASSISTANT, "Hello")
+    _add(chat_module, chat, SenderType.USER, "Contents of `a.md`", hidden=True)
+    _add(chat_module, chat, SenderType.USER, "Review `a.md`")
+    _add(chat_module, chat, SenderType.ASSISTANT, "Looks good")
+
+    assert chat_module.undo_last_turn(chat)
+
+    assert [m.text for m in chat.messages] == ["Hi", "Hello"]
+
+
+def test_undo_last_turn_keeps_context_added_after_turn(chat_module):
+    chat = chat_module.Chat()
+    _add(chat_module, chat, SenderType.USER, "Hi")
+    _add(chat_module, chat, SenderType.ASSISTANT, "Hello")
+    chat_module.add_context(chat, "My environment")
+
+    assert chat_module.undo_last_turn(chat)
+
+    assert [m.text for m in chat.messages] == ["My environment"]
+
+
+def test_undo_last_turn_keeps_compacted_summary(chat_module):
+    chat = chat_module.Chat()
+    _add(chat_module, chat, SenderType.USER, "Hi")
+    _add(chat_module, chat, SenderType.ASSISTANT, "Hello")
+    chat_module.compact_chat(chat, "User said hi.")
+
+    assert not chat_module.undo_last_turn(chat)
+    assert len(chat.messages) == 1
+
+
+def test_get_candidate_count_reads_chosen_profile(chat_module, mocker: MockerFixture):
+    mocker.patch.object(chat_module, "completion_settings", {})
+    mocker.patch.object(chat_module.config, "profiles", {"multi": {"n_candidates": 3}})
+    chat = chat_module.Chat()
+    assert chat_module.get_candidate_count(chat) == 1
+
+    chat.context.profile = "multi"
+
+    assert chat_module.get_candidate_count(chat) == 3
+
+
+def test_get_transcript_keeps_recent_messages_and_shortens_context(
+    chat_module, mocker: MockerFixture
+):
+    task_model = mocker.MagicMock()
+    task_model.count_tokens.side_effect = lambda text: len(text.split())
+    chat = chat_module.Chat()
+    _add(chat_module, chat, SenderType.USER, "An old question about many things")
+    _add(chat_module, chat, SenderType.ASSISTANT, "An old answer")
+    _add(chat_module, chat, SenderType.USER, "Contents of `a.md`:\n\nlong", hidden=True)
+    _add(chat_module, chat, SenderType.USER, "Review it")
+
+    transcript = chat_module._get_transcript(task_model, chat.messages, 12)
+
+    assert transcript == (
+        "assistant: An old answer\n\n"
+        "user: Contents of `a.md`: (omitted)\n\n"
+        "user: Review it"
+    )
+
+
+def test_reload_model_loads_current_system_prompt(chat_module, mocker: MockerFixture):
+    model = mocker.patch.object(chat_module, "model")
+    chat = chat_module.Chat()
+    chat_module.set_system_prompt(chat, "You are a pirate.")
+
+    chat_module.reload_model(chat)
+
+    assert "You are a pirate." in model.load.call_args.args[0]
@@ -4,7 +4,20 @@ import os
 import pytest
 from pytest_mock import MockerFixture
 
-from arey.config import create_or_get_config_file, get_config
+from arey.config import (
+    ConfigMigration,
+    HistoryConfig,
+    create_or_get_config_file,
+    find_workspace_config,
+    get_config,
+    get_profile,
+    get_section,
+    merge_config,
+    migrate_config,
+    parse_yaml,
+    resolve_profile,
+)
+from arey.error import AreyError
 
 
 @pytest.fixture(scope="session")
@@ -78,3 +91,164 @@ def test_get_config_return_config_for_valid_schema(
     assert config1 is not None
     assert config1 == config2
     assert len(config1.profiles) == 3
+
+
+def test_parse_yaml_reports_error_location():
+    content = "models:\n  a:\n    path: x\n  b: [1, 2\nchat:\n"
+
+    with pytest.raises(AreyError) as e:
+        parse_yaml(content, "arey.yml")
+
+    assert e.value.category == "config"
+    assert "arey.yml at line 5, column 5" in e.value.message
+    assert "   4 |   b: [1, 2" in e.value.message
+
+
+def test_migrate_config_upgrades_and_backs_up(tmp_path, mocker: MockerFixture):
+    def rename_chat(content: dict) -> None:
+        content["chat"] = content.pop("conversation")
+
+    migration = ConfigMigration(1, "Rename `conversation` to `chat`.", rename_chat)
+    mocker.patch("arey.config.CONFIG_VERSION", 2)
+    mocker.patch("arey.config._migrations", [migration])
+    config_file = tmp_path / "arey.yml"
+    config_file.write_text("version: 1\nconversation:\n  model: m\n")
+    content = parse_yaml(config_file.read_text(), str(config_file))
+
+    messages = migrate_config(str(config_file), content)
+
+    assert messages[0] == "Rename `conversation` to `chat`."
+    assert content == {"version": 2, "chat": {"model": "m"}}
+    assert parse_yaml(config_file.read_text(), str(config_file)) == content
+    assert (tmp_path / "arey.yml.v1.bak").read_text().startswith("version: 1")
+
+
+def test_migrate_config_throws_for_newer_version():
+    with pytest.raises(AreyError) as e:
+        migrate_config("arey.yml", {"version": 100})
+
+    assert "not supported" in e.value.message
+
+
+def test_get_profile_warns_and_passes_unknown_setting():
+    warnings = []
+
+    profile = get_profile("precise", {"temprature": 0.7, "num_ctx": 8192}, warnings)
+
+    assert profile == {"temprature": 0.7, "num_ctx": 8192}
+    assert "Did you mean `temperature`?" in warnings[0]
+    assert "`num_ctx`" in warnings[1]
+
+
+def test_get_profile_merges_extra_settings():
+    warnings = []
+
+    profile = get_profile(
+        "precise", {"top_k": 40, "extra": {"dry_multiplier": 0.8}}, warnings
+    )
+
+    assert profile == {"top_k": 40, "dry_multiplier": 0.8}
+    assert warnings == []
+
+
+def test_get_section_throws_for_unknown_setting():
+    assert get_section(HistoryConfig, "history", None) == HistoryConfig()
+
+    with pytest.raises(AreyError) as e:
+        get_section(HistoryConfig, "history", {"max_entires": 10})
+
+    assert e.value.category == "config"
+    assert "Did you mean `max_entries`?" in e.value.message
+
+
+def test_resolve_profile_merges_base_profiles():
+    profiles = {
+        "base": {"temperature": 0.7, "top_k": 40, "extra": {"dry_multiplier": 0.8}},
+        "creative": {"extends": "base", "temperature": 0.9, "extra": {"seed": 1}},
+        "story": {"extends": "creative", "top_k": 0},
+    }
+
+    assert resolve_profile("story", profiles) == {
+        "temperature": 0.9,
+        "top_k": 0,
+        "extra": {"dry_multiplier": 0.8, "seed": 1},
+    }
+
+
+def test_resolve_profile_overrides_base_max_tokens_with_latency():
+    profiles = {
+        "base": {"temperature": 0.7, "max_tokens": 4096},
+        "short": {"extends": "base", "latency": "snappy"},
+    }
+
+    profile = get_profile("short", resolve_profile("short", profiles), [])
+
+    assert profile == {"temperature": 0.7, "max_tokens": 256}
+
+
+def test_resolve_profile_throws_for_cycle():
+    profiles = {"a": {"extends": "b"}, "b": {"extends": "a"}}
+
+    with pytest.raises(AreyError) as e:
+        resolve_profile("a", profiles)
+
+    assert "a -> b -> a" in e.value.message
+
+
+def test_merge_config_overlays_workspace_settings():
+    content = {
+        "models": {"a": {"path": "a.gguf"}, "b": {"path": "b.gguf"}},
+        "chat": {"model": "a", "profile": "precise"},
+    }
+    overlay = {
+        "version": 1,
+        "models": {"c": {"path": "c.gguf"}},
+        "allowed_models": ["b", "c"],
+        "chat": {"model": "c"},
+    }
+
+    result = merge_config(content, overlay)
+
+    assert result["models"] == {"b": {"path": "b.gguf"}, "c": {"path": "c.gguf"}}
+    assert result["chat"] == {"model": "c", "profile": "precise"}
+    assert "allowed_models" not in result
+
+
+def test_merge_config_throws_for_unknown_allowed_model():
+    with pytest.raises(AreyError) as e:
+        merge_config({"models": {"a": {}}}, {"allowed_models": ["x"]})
+
+    assert e.value.category == "config"
+
+
+def test_merge_config_throws_for_denied_workspace_settings():
+    overlay = {
+        "env": {"commands": ["curl example.com"]},
+        "chat": {"settings": {"host": "http://example.com", "n_ctx": 2048}},
+    }
+
+    with pytest.raises(AreyError) as e:
+        merge_config({"chat": {"model": "a"}}, overlay)
+
+    assert "`env, chat.settings.host`" in e.value.message
+
+
+def test_merge_config_throws_for_workspace_content_filter():
+    overlay = {"chat": {"content_filter": {"level": "off"}}}
+
+    with pytest.raises(AreyError) as e:
+        merge_config({"chat": {"model": "a"}}, overlay)
+
+    assert "`chat.content_filter`" in e.value.message
+
+
+def test_find_workspace_config_prefers_current_dir_over_git_root(tmp_path):
+    (tmp_path / ".git").mkdir()
+    (tmp_path / ".arey.yml").write_text("chat: {}")
+    project = tmp_path / "project"
+    project.mkdir()
+
+    assert find_workspace_config(str(project)) == str(tmp_path / ".arey.yml")
+
+    (project / ".arey.yml").write_text("chat: {}")
+    assert find_workspace_config(str(project)) == str(project / ".arey.yml")
@@ -0,0 +1,28 @@
+"""Tests for the doctor command services."""
+import pytest
+from pytest_mock import MockerFixture
+
+from arey.doctor import _check_ollama
+
+pytest.importorskip("ollama")
+
+
+def test_check_ollama_matches_default_tag(mocker: MockerFixture):
+    client = mocker.patch("ollama.Client")
+    client.return_value.list.return_value = {"models": [{"name": "llama3:latest"}]}
+    settings = {"headers": {"Authorization": "Bearer token"}}
+
+    result = _check_ollama("llama3", {"name": "llama3"}, settings)
+
+    assert result.ok
+    assert client.call_args.kwargs["headers"] == {"Authorization": "Bearer token"}
+
+
+def test_check_ollama_fails_for_missing_model(mocker: MockerFixture):
+    client = mocker.patch("ollama.Client")
+    client.return_value.list.return_value = {"models": [{"name": "llama3:8b"}]}
+
+    result = _check_ollama("llama3", {"name": "llama3"}, {})
+
+    assert not result.ok
+    assert "ollama pull llama3" in result.fix
@@ -0,0 +1,34 @@
+"""Tests for extracting text from documents."""
+import pytest
+
+from arey.error import AreyError
+from arey.platform.documents import extract_text
+
+
+def test_extract_text_reads_plain_text(tmp_path):
+    path = tmp_path / "notes.md"
+    path.write_text("# Notes\n\nHello", encoding="utf-8")
+
+    assert extract_text(str(path), 7) == "# Notes"
+
+
+def test_extract_text_throws_for_binary_file(tmp_path):
+    path = tmp_path / "image.png"
+    path.write_bytes(b"\x89PNG\r\n\x1a\n\x00\x00")
+    text_path = tmp_path / "data.bin"
+    text_path.write_bytes(b"abc\x00def")
+
+    with pytest.raises(AreyError) as e:
+        extract_text(str(path), 100)
+    assert "isn't UTF-8 text" in e.value.message
+
+    with pytest.raises(AreyError) as e:
+        extract_text(str(text_path), 100)
+    assert "is binary" in e.value.message
+
+
+def test_extract_text_throws_for_directory(tmp_path):
+    with pytest.raises(AreyError) as e:
+        extract_text(str(tmp_path), 100)
+
+    assert str(tmp_path) in e.value.message
@@ -0,0 +1,29 @@
+"""Tests for the idle timer."""
+import time
+
+from arey.platform.idle import IdleTimer
+
+
+def test_idle_timer_stays_fired_when_restarted():
+    freed = []
+    timer = IdleTimer(0.01, lambda: freed.append(True))
+
+    timer.start()
+    time.sleep(0.2)
+    timer.start()  # e.g., Ctrl-C at the prompt before the next message
+    timer.stop()
+
+    assert freed == [True]
+    assert timer.fired
+
+
+def test_idle_timer_stop_cancels_callback():
+    freed = []
+    timer = IdleTimer(0.1, lambda: freed.append(True))
+
+    timer.start()
+    timer.stop()
+    time.sleep(0.2)
+
+    assert freed == []
+    assert not timer.fired
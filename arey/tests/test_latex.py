@@ -0,0 +1,42 @@
+"""Tests for rendering LaTeX math."""
+from arey.platform.latex import render_math
+
+
+def test_render_math_converts_symbols_and_scripts():
+    text = r"Since $x^2 \leq \alpha$ and \(x_1 \neq 0\)"
+
+    result = render_math(text)
+
+    assert result == "Since x² ≤ α and x₁ ≠ 0"
+
+
+def test_render_math_converts_scripts_around_symbols():
+    text = r"$\sum_{i=1}^n i$ and $\int_0^1 x$"
+
+    result = render_math(text)
+
+    assert result == "∑ᵢ₌₁ⁿ i and ∫₀¹ x"
+
+
+def test_render_math_keeps_symbol_in_script():
+    text = r"$\int_0^\infty f$, $x^\alpha$ and $x^{\alpha}$"
+
+    result = render_math(text)
+
+    assert result == text
+
+
+def test_render_math_keeps_unsupported_commands():
+    text = r"$\mathcal{O}(n)$"
+
+    result = render_math(text)
+
+    assert result == text
+
+
+def test_render_math_skips_prices_and_code():
+    text = "Costs $5 or $10, `$x^2$` stays"
+
+    result = render_math(text)
+
+    assert result == text
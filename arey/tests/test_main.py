@@ -0,0 +1,153 @@
+"""Integration tests for the command line.
+
+Runs the `arey` commands in a subprocess with an isolated config and replays
+model responses from a cassette, so no model is loaded.
+"""
+import json
+import os
+import re
+import subprocess
+import sys
+
+import pytest
+
+from arey.platform.cassette import CASSETTE_VERSION
+from arey.prompt import get_prompt
+
+ANSI_ESCAPE = re.compile(r"\x1b\[[0-9;?]*[A-Za-z]")
+
+CONFIG = """\
+version: 1
+models:
+  test-model:
+    path: {model_path}
+    template: chatml
+chat:
+  model: test-model
+task:
+  model: test-model
+"""
+
+
+def _response(text: str) -> dict:
+    return {
+        "text": text,
+        "finish_reason": "stop",
+        "metrics": {
+            "prompt_tokens": 10,
+            "prompt_eval_latency_ms": 1.0,
+            "completion_tokens": 2,
+            "completion_runs": 1,
+            "completion_latency_ms": 2.0,
+            "prompt_cached_tokens": 0,
+        },
+    }
+
+
+@pytest.fixture
+def workspace(tmp_path):
+    # Replay doesn't need the model file
+    (tmp_path / "arey").mkdir()
+    (tmp_path / "arey" / "arey.yml").write_text(
+        CONFIG.format(model_path=_get_model_path(tmp_path))
+    )
+    return tmp_path
+
+
+def _get_model_path(workspace) -> str:
+    return (workspace / "model.gguf").as_posix()
+
+
+def _write_cassette(path, interactions) -> str:
+    recording = {
+        "context_size": 4096,
+        "interactions": [
+            {"prompt": prompt, "settings": {}, "responses": [_response(text)]}
+            for prompt, text in interactions
+        ],
+    }
+    content = {
+        "version": CASSETTE_VERSION,
+        "models": {_get_model_path(path.parent): recording},
+    }
+    path.write_text(json.dumps(content))
+    return str(path)
+
+
+def _run_arey(workspace, *args: str, stderr: bool = False) -> str:
+    env = os.environ | {
+        "XDG_CONFIG_HOME": str(workspace),
+        "XDG_DATA_HOME": str(workspace),
+        "NO_COLOR": "1",
+    }
+    result = subprocess.run(
+        [sys.executable, "-m", "arey.main", *args],
+        cwd=workspace,
+        env=env,
+        capture_output=True,
+        text=True,
+        encoding="utf-8",
+        timeout=60,
+    )
+    assert result.returncode == 0, result.stderr
+    return ANSI_ESCAPE.sub("", result.stderr if stderr else result.stdout)
+
+
+def test_ask_prints_response(workspace):
+    prompt = get_prompt("chatml").get("task", {"user_query": "Hi", "chat_history": ""})
+    cassette = _write_cassette(
+        workspace / "ask.json", [(prompt, "Hello from the test model.")]
+    )
+
+    output = _run_arey(workspace, "--replay", cassette, "ask", "Hi")
+
+    assert "Hello from the test model." in output
+    assert "◼ Completed." in output
+
+
+def test_ask_emits_only_events_on_stdout(workspace):
+    prompt = get_prompt("chatml").get("task", {"user_query": "Hi", "chat_history": ""})
+    cassette = _write_cassette(workspace / "ask.json", [(prompt, "Hello.")])
+    (workspace / ".arey.yml").write_text("profiles:\n  typo:\n    temprature: 0.5\n")
+    args = ("--replay", cassette, "ask", "--emit", "ndjson", "Hi")
+
+    output = _run_arey(workspace, *args)
+    errors = _run_arey(workspace, *args, stderr=True)
+
+    events = [json.loads(line) for line in output.splitlines()]
+    assert [e["event"] for e in events] == ["chunk", "metrics", "done"]
+    assert "unknown setting `temprature`" in errors
+
+
+def test_run_plays_script_turns(workspace):
+    chatml = get_prompt("chatml")
+    history = chatml.get_message("user", "Hi") + chatml.get_message(
+        "assistant", "Hello there."
+    )
+    first = chatml.get("chat", {"user_query": "Hi", "chat_history": ""})
+    second = chatml.get("chat", {"user_query": "Bye", "chat_history": history})
+    cassette = _write_cassette(
+        workspace / "run.json", [(first, "Hello there."), (second, "Goodbye.")]
+    )
+    script = workspace / "script.yml"
+    script.write_text("turns:\n  - Hi\n  - Bye\n")
+    transcript = workspace / "transcript.md"
+
+    output = _run_arey(
+        workspace, "--replay", cassette, "run", str(script), "-o", str(transcript)
+    )
+
+    assert "Playing 2 turns" in output
+    assert output.index("Hello there.") < output.index("Goodbye.")
+    assert transcript.read_text(encoding="utf-8") == (
+        "## User\n\nHi\n\n## Assistant\n\nHello there.\n\n"
+        "## User\n\nBye\n\n## Assistant\n\nGoodbye.\n\n"
+    )
+
+
+def test_ask_reports_error_for_unexpected_prompt(workspace):
+    cassette = _write_cassette(workspace / "ask.json", [("Other prompt", "Hello.")])
+
+    output = _run_arey(workspace, "--replay", cassette, "ask", "Hi")
+
+    assert "Prompt doesn't match recorded interaction #1" in output
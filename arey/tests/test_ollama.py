@@ -0,0 +1,39 @@
+"""Tests for the Ollama completion model."""
+import pytest
+from pytest_mock import MockerFixture
+
+pytest.importorskip("ollama")
+
+from ollama import ResponseError  # noqa: E402
+
+from arey.error import ProviderError  # noqa: E402
+from arey.platform._ollama import OllamaBaseModel  # noqa: E402
+
+
+def test_complete_sends_settings_as_options(mocker: MockerFixture):
+    model = OllamaBaseModel("llama3")
+    model.client = mocker.MagicMock()
+    model.client.generate.return_value = iter([{"response": "Hi", "done": True}])
+
+    response = list(
+        model.complete("Hello", {"max_tokens": 256, "temperature": 0.2, "seed": 1})
+    )
+
+    options = dict(model.client.generate.call_args.kwargs["options"])
+    assert response[0].text == "Hi"
+    assert options["num_predict"] == 256
+    assert options["temperature"] == 0.2
+    assert options["seed"] == 1
+    assert "raw" not in options
+
+
+def test_load_raises_auth_error_for_denied_access(mocker: MockerFixture):
+    client = mocker.patch("arey.platform._ollama.Client").return_value
+    client.generate.side_effect = ResponseError("unauthorized", 401)
+    model = OllamaBaseModel("llama3", {"host": "https://ollama.example.com"})
+
+    with pytest.raises(ProviderError) as e:
+        model.load("")
+
+    assert e.value.reason == "auth"
+    assert "https://ollama.example.com" in e.value.message
@@ -1,4 +1,5 @@
 """Tests for the prompts."""
+from arey.prompt import check_chat_template, get_prompt
 
 
 def prompts_should_be_valid():
@@ -8,3 +9,12 @@ def prompts_should_be_valid():
 def get_prompt_should_throw_on_invalid_template_name():
     # with pytest.raises(Argumen
     pass
+
+
+def test_check_chat_template_suggests_matching_template():
+    chat_template = "{% for m in messages %}<|im_start|>{{ m.role }}{% endfor %}"
+
+    warning = check_chat_template(get_prompt("llama3"), chat_template)
+
+    assert warning and "Set `template` to one of chatml" in warning
+    assert check_chat_template(get_prompt("chatml"), chat_template) is None